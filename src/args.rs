@@ -1,8 +1,8 @@
-use std::{
-	env::Args,
-	iter::{Peekable, repeat_n},
-};
+use std::{collections::VecDeque, iter::repeat_n};
 
+use crate::error::{Error, Fallible};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ArgType {
 	Flag,
 	String,
@@ -46,7 +46,7 @@ impl Arg {
 	}
 
 	pub fn matches(&self, arg: &str) -> bool {
-		self.long.map_or(false, |e| e == arg) || self.short.map_or(false, |e| e == arg)
+		self.long.is_some_and(|e| e == arg) || self.short.is_some_and(|e| e == arg)
 	}
 
 	pub const fn short_len(&self) -> usize {
@@ -58,24 +58,60 @@ impl Arg {
 	}
 }
 
+const DEFAULT_USAGE: &str = "Usage: matey [OPTIONS]... [-i] FILE
+  or:  matey [OPTIONS]... --color COLOR
+  or:  matey candidates FILE
+  or:  matey cache list|show|prune|clear
+  or:  matey watch [OPTIONS]... [-i] FILE
+  or:  matey check [FILE]...
+Generate theme for FILE or COLOR and write configs with given templates
+
+The path of every config that changed is printed on stdout, one per line.
+Configs that already hold the output are left alone and their hooks don't
+run. With --dry-run nothing is written, the configs that would change are
+printed instead";
+
 pub struct ArgParserBuilder {
-	args: Args,
+	args: VecDeque<String>,
+	usage: &'static str,
 	opts: Vec<Arg>,
-	last_opt: Arg,
+	last_opt: Option<Arg>,
+	repeat_last_opt: bool,
 	priority_opts: Vec<Arg>,
 }
 
 impl ArgParserBuilder {
-	pub fn new(mut args: Args, last_opt: Arg) -> Self {
-		args.next();
+	/// `args` starts with the name of the program, as [`std::env::args`] does.
+	pub fn new(args: impl IntoIterator<Item = String>, last_opt: Arg) -> Self {
+		let mut builder = ArgParserBuilder::without_last_opt(args);
+		builder.last_opt = Some(last_opt);
+		builder
+	}
+
+	/// For commands that only take options.
+	pub fn without_last_opt(args: impl IntoIterator<Item = String>) -> Self {
 		ArgParserBuilder {
-			last_opt,
-			args,
+			last_opt: None,
+			repeat_last_opt: false,
+			args: args.into_iter().skip(1).collect(),
+			usage: DEFAULT_USAGE,
 			priority_opts: Vec::new(),
 			opts: Vec::new(),
 		}
 	}
 
+	pub fn usage(mut self, usage: &'static str) -> Self {
+		self.usage = usage;
+		self
+	}
+
+	/// Every argument that isn't an option is then given to the last option,
+	/// as in `matey check FILE...`.
+	pub fn repeat_last_opt(mut self) -> Self {
+		self.repeat_last_opt = true;
+		self
+	}
+
 	pub fn add_opt(mut self, arg: Arg) -> Self {
 		self.opts.push(arg);
 		self
@@ -88,29 +124,49 @@ impl ArgParserBuilder {
 
 	pub fn build(self) -> ArgParser {
 		ArgParser {
-			args: self.args.peekable(),
+			args: self.args,
+			only_positional: false,
+			usage: self.usage,
 			opts: self.opts,
 			last_opt: self.last_opt,
+			repeat_last_opt: self.repeat_last_opt,
 			last_opt_read: false,
 			priority_opts: self.priority_opts,
 		}
 	}
 }
 
+/// Reads options the usual way: `-t FILE`, `--template FILE`,
+/// `--template=FILE`, `-tFILE`, and flags grouped as in `-ul`. Other
+/// arguments, and everything after `--`, go to the last option. Priority
+/// options, such as `--help`, are looked for first, and are then the only
+/// option given.
 pub struct ArgParser {
-	args: Peekable<Args>,
+	/// What is left to read, including the rest of a group of short flags.
+	args: VecDeque<String>,
+	only_positional: bool,
+	usage: &'static str,
 	opts: Vec<Arg>,
-	last_opt: Arg,
+	last_opt: Option<Arg>,
+	repeat_last_opt: bool,
 	priority_opts: Vec<Arg>,
 	last_opt_read: bool,
 }
 
 impl ArgParser {
-	pub fn new(args: Args, opts: Vec<Arg>, priority_opts: Vec<Arg>, last_opt: Arg) -> Self {
+	pub fn new(
+		args: impl IntoIterator<Item = String>,
+		opts: Vec<Arg>,
+		priority_opts: Vec<Arg>,
+		last_opt: Arg,
+	) -> Self {
 		ArgParser {
-			args: args.peekable(),
+			args: args.into_iter().collect(),
+			only_positional: false,
+			usage: DEFAULT_USAGE,
 			opts,
-			last_opt,
+			last_opt: Some(last_opt),
+			repeat_last_opt: false,
 			last_opt_read: false,
 			priority_opts,
 		}
@@ -122,7 +178,12 @@ impl ArgParser {
 
 	pub fn emit_help(&self) {
 		let mut largest = 0;
-		for arg in &self.opts {
+		for arg in self
+			.opts
+			.iter()
+			.chain(self.priority_opts.iter())
+			.chain(self.last_opt.iter())
+		{
 			let current = ArgParser::arg_help_len(arg);
 
 			if current > largest {
@@ -130,14 +191,13 @@ impl ArgParser {
 			}
 		}
 
-		println!("Usage: matey [OPTIONS]... [-i] FILE");
-		println!("Generate theme for FILE and write configs with given templates\n");
+		println!("{}\n", self.usage);
 
 		for opt in self
 			.opts
 			.iter()
 			.chain(self.priority_opts.iter())
-			.chain([&self.last_opt])
+			.chain(self.last_opt.iter())
 		{
 			match (opt.short, opt.long) {
 				(Some(short), Some(long)) => print!("  {}, {}   ", short, long),
@@ -150,42 +210,241 @@ impl ArgParser {
 			println!("{}", opt.help);
 		}
 	}
-}
 
-impl Iterator for ArgParser {
-	type Item = (&'static str, Option<String>);
+	fn next_raw(&mut self) -> Option<String> {
+		self.args.pop_front()
+	}
 
-	fn next(&mut self) -> Option<Self::Item> {
-		let with_next_arg = |arg: &Arg, got: &str, args: &mut Peekable<Args>| {
-			if let Some(value) = args.next() {
-				Some((arg.name, Some(value)))
-			} else {
-				panic!("{} needs a value", got);
+	/// The first priority option given before `--`, if any.
+	fn find_priority_opt(&self) -> Option<&'static str> {
+		self.args
+			.iter()
+			.take_while(|arg| *arg != "--")
+			.find_map(|arg| {
+				self.priority_opts
+					.iter()
+					.find(|opt| opt.matches(arg))
+					.map(|opt| opt.name)
+			})
+	}
+
+	fn find_opt(&self, name: &str) -> Option<&Arg> {
+		self.priority_opts
+			.iter()
+			.chain(&self.opts)
+			.chain(&self.last_opt)
+			.find(|arg| arg.matches(name))
+	}
+
+	fn positional(&mut self, value: String) -> Fallible<(&'static str, Option<String>)> {
+		match &self.last_opt {
+			Some(last_opt) if self.repeat_last_opt || !self.last_opt_read => {
+				self.last_opt_read = true;
+				Ok((last_opt.name, Some(value)))
 			}
+			_ => Err(Error::Usage(format!("unexpected argument {}", value))),
+		}
+	}
+
+	fn option(&mut self, arg: String) -> Fallible<(&'static str, Option<String>)> {
+		let (mut name, mut value) = match arg.split_once('=') {
+			Some((name, value)) if name.starts_with("--") => {
+				(name.to_string(), Some(value.to_string()))
+			}
+			_ => (arg, None),
 		};
 
-		if let Some(next_arg) = self.args.next() {
-			if let Some(arg) = self.priority_opts.iter().find(|arg| arg.matches(&next_arg)) {
-				Some((arg.name, Some(next_arg)))
-			} else if self.args.peek().is_none() && !self.last_opt_read {
-				self.last_opt_read = true;
-				Some((self.last_opt.name, Some(next_arg)))
-			} else if self.last_opt.matches(&next_arg) {
-				self.last_opt_read = true;
-				with_next_arg(&self.last_opt, &next_arg, &mut self.args)
-			} else if let Some(arg) = self.opts.iter().find(|arg| arg.matches(&next_arg)) {
-				match arg.type_ {
-					ArgType::Flag => Some((arg.name, None)),
-					ArgType::String => with_next_arg(&arg, &next_arg, &mut self.args),
-				}
+		// `-ul` is `-u -l`, and `-tFILE` is `-t FILE`
+		if !name.starts_with("--")
+			&& let Some((split, _)) = name.char_indices().nth(2)
+		{
+			let rest = name.split_off(split);
+			let takes_value = self
+				.find_opt(&name)
+				.is_some_and(|arg| arg.type_ == ArgType::String);
+			if takes_value {
+				value = Some(rest);
 			} else {
-				panic!("unknown argument {next_arg}");
-			}
-		} else {
-			if !self.last_opt_read {
-				panic!("{} must be specified", self.last_opt.name);
+				self.args.push_front(format!("-{}", rest));
 			}
-			None
 		}
+
+		let Some(arg) = self.find_opt(&name) else {
+			return Err(Error::Usage(format!("unknown option {}", name)));
+		};
+		let (arg_name, type_) = (arg.name, arg.type_);
+		if self
+			.last_opt
+			.as_ref()
+			.is_some_and(|last_opt| last_opt.name == arg_name)
+		{
+			self.last_opt_read = true;
+		}
+
+		match (type_, value) {
+			(ArgType::Flag, None) => Ok((arg_name, None)),
+			(ArgType::Flag, Some(_)) => Err(Error::Usage(format!("{} doesn't take a value", name))),
+			(ArgType::String, Some(value)) => Ok((arg_name, Some(value))),
+			(ArgType::String, None) => match self.next_raw() {
+				Some(value) => Ok((arg_name, Some(value))),
+				None => Err(Error::Usage(format!("{} needs a value", name))),
+			},
+		}
+	}
+}
+
+impl Iterator for ArgParser {
+	type Item = Fallible<(&'static str, Option<String>)>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		if !self.only_positional
+			&& let Some(name) = self.find_priority_opt()
+		{
+			self.args.clear();
+			return Some(Ok((name, None)));
+		}
+
+		let mut arg = self.next_raw()?;
+		if arg == "--" && !self.only_positional {
+			self.only_positional = true;
+			arg = self.next_raw()?;
+		}
+
+		Some(
+			if self.only_positional || !arg.starts_with('-') || arg == "-" {
+				self.positional(arg)
+			} else {
+				self.option(arg)
+			},
+		)
+	}
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use crate::{
+		args::{Arg, ArgParser, ArgParserBuilder, ArgType},
+		error::Error,
+	};
+
+	#[allow(dead_code)]
+	fn parse(
+		args: &[&str],
+		repeat_last_opt: bool,
+	) -> Result<Vec<(&'static str, Option<String>)>, String> {
+		let args = ["matey"].iter().chain(args).map(|arg| arg.to_string());
+		let mut builder = ArgParserBuilder::new(
+			args,
+			Arg::new("image", Some("-i"), Some("--image"), "", ArgType::String),
+		)
+		.add_opt(Arg::new(
+			"template",
+			Some("-t"),
+			Some("--template"),
+			"",
+			ArgType::String,
+		))
+		.add_opt(Arg::new(
+			"use-cache",
+			Some("-u"),
+			Some("--use-cache"),
+			"",
+			ArgType::Flag,
+		))
+		.add_opt(Arg::new(
+			"light",
+			Some("-l"),
+			Some("--light"),
+			"",
+			ArgType::Flag,
+		))
+		.add_priority_opt(Arg::new(
+			"help",
+			Some("-h"),
+			Some("--help"),
+			"",
+			ArgType::Flag,
+		));
+		if repeat_last_opt {
+			builder = builder.repeat_last_opt();
+		}
+
+		builder
+			.build()
+			.collect::<Result<Vec<_>, _>>()
+			.map_err(|e| e.to_string())
+	}
+
+	#[allow(dead_code)]
+	fn opt(name: &'static str, value: Option<&str>) -> (&'static str, Option<String>) {
+		(name, value.map(str::to_string))
+	}
+
+	#[test]
+	fn test_forms() {
+		let expected = [
+			opt("template", Some("a")),
+			opt("use-cache", None),
+			opt("light", None),
+			opt("image", Some("img.png")),
+		];
+		for args in [
+			&["-t", "a", "-u", "-l", "img.png"][..],
+			&["--template=a", "-ul", "img.png"],
+			&["-ta", "-lu", "img.png"][..],
+			&["-ult", "a", "--", "img.png"],
+			&["img.png", "--template", "a", "-u", "--light"],
+		] {
+			let mut parsed = parse(args, false).unwrap();
+			parsed.sort_by_key(|(name, _)| expected.iter().position(|(e, _)| e == name));
+			assert_eq!(parsed, expected, "{:?}", args);
+		}
+
+		// values are taken as they are, even when they look like options
+		assert_eq!(
+			parse(&["-t", "-l", "--image=a=b"], false).unwrap(),
+			[opt("template", Some("-l")), opt("image", Some("a=b"))]
+		);
+		assert_eq!(
+			parse(&["-t", "a", "-t", "b", "--", "-l"], false).unwrap(),
+			[
+				opt("template", Some("a")),
+				opt("template", Some("b")),
+				opt("image", Some("-l"))
+			]
+		);
+		assert_eq!(
+			parse(&["a", "-u", "b", "--", "-c"], true).unwrap(),
+			[
+				opt("image", Some("a")),
+				opt("use-cache", None),
+				opt("image", Some("b")),
+				opt("image", Some("-c"))
+			]
+		);
+	}
+
+	#[test]
+	fn test_errors() {
+		for (args, message) in [
+			(&["--bogus"][..], "unknown option --bogus"),
+			(&["-ux"], "unknown option -x"),
+			(&["-t"], "-t needs a value"),
+			(&["--light=yes"], "--light doesn't take a value"),
+			(&["a", "b"], "unexpected argument b"),
+			(&["-i", "a", "b"], "unexpected argument b"),
+		] {
+			assert_eq!(parse(args, false), Err(message.to_string()));
+		}
+
+		// the help is given whatever else is wrong
+		assert_eq!(
+			parse(&["--bogus", "-t", "a", "--help"], false),
+			Ok(vec![opt("help", None)])
+		);
+		assert_eq!(
+			parse(&["--", "--help"], false),
+			Ok(vec![opt("image", Some("--help"))])
+		);
 	}
 }
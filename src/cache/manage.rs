@@ -0,0 +1,165 @@
+use std::time::{Duration, SystemTime};
+
+use super::CacheEntry;
+
+/// Formats the time as `YYYY-MM-DD HH:MM` in UTC.
+pub fn format_time(time: SystemTime) -> String {
+	let secs = time
+		.duration_since(SystemTime::UNIX_EPOCH)
+		.map_or(0, |d| d.as_secs()) as i64;
+	let (days, secs_of_day) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+
+	// days to civil date, see http://howardhinnant.github.io/date_algorithms.html
+	let z = days + 719468;
+	let era = z.div_euclid(146097);
+	let doe = z.rem_euclid(146097);
+	let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+	let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+	let mp = (5 * doy + 2) / 153;
+	let day = doy - (153 * mp + 2) / 5 + 1;
+	let month = if mp < 10 { mp + 3 } else { mp - 9 };
+	let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+	format!(
+		"{:04}-{:02}-{:02} {:02}:{:02}",
+		year,
+		month,
+		day,
+		secs_of_day / 3600,
+		secs_of_day % 3600 / 60
+	)
+}
+
+/// Parses ages such as `90s`, `30m`, `12h`, `30d` or `2w`.
+pub fn parse_age(age: &str) -> Option<Duration> {
+	let split = age.find(|c: char| !c.is_ascii_digit())?;
+	let (amount, unit) = age.split_at(split);
+	let unit = match unit {
+		"s" => 1,
+		"m" => 60,
+		"h" => 60 * 60,
+		"d" => 24 * 60 * 60,
+		"w" => 7 * 24 * 60 * 60,
+		_ => return None,
+	};
+
+	Some(Duration::from_secs(
+		amount.parse::<u64>().ok()?.checked_mul(unit)?,
+	))
+}
+
+/// Parses sizes such as `512`, `100K`, `10M` or `1G`, in powers of 1024.
+pub fn parse_size(size: &str) -> Option<u64> {
+	let (amount, unit) = match size.find(|c: char| !c.is_ascii_digit()) {
+		Some(split) => size.split_at(split),
+		None => (size, ""),
+	};
+	let unit = match unit {
+		"" | "B" => 1,
+		"K" | "KB" => 1 << 10,
+		"M" | "MB" => 1 << 20,
+		"G" | "GB" => 1 << 30,
+		_ => return None,
+	};
+
+	amount.parse::<u64>().ok()?.checked_mul(unit)
+}
+
+/// The entries [`Cacher::prune`](super::Cacher::prune) removes: the ones
+/// older than `older_than`, then the oldest ones until the rest takes at most
+/// `max_size` bytes. `entries` are sorted from the most recently written, so
+/// once one doesn't fit, it and every entry after it are removed.
+pub fn select_pruned(
+	entries: Vec<CacheEntry>,
+	now: SystemTime,
+	older_than: Option<Duration>,
+	max_size: Option<u64>,
+) -> Vec<CacheEntry> {
+	let mut total_size = 0;
+	let mut full = false;
+
+	entries
+		.into_iter()
+		.filter(|entry| {
+			let too_old = older_than.is_some_and(|max_age| {
+				now.duration_since(entry.modified)
+					.is_ok_and(|age| age > max_age)
+			});
+
+			if !too_old && !full {
+				full = max_size.is_some_and(|max_size| total_size + entry.size > max_size);
+				if !full {
+					total_size += entry.size;
+				}
+			}
+			too_old || full
+		})
+		.collect()
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use crate::cache::{
+		CacheEntry,
+		manage::{format_time, parse_age, parse_size, select_pruned},
+	};
+	#[allow(unused_imports)]
+	use std::{
+		path::PathBuf,
+		time::{Duration, SystemTime},
+	};
+
+	#[test]
+	fn test_parse() {
+		assert_eq!(parse_age("90s"), Some(Duration::from_secs(90)));
+		assert_eq!(parse_age("12h"), Some(Duration::from_secs(12 * 3600)));
+		assert_eq!(parse_age("2w"), Some(Duration::from_secs(14 * 86400)));
+		for age in ["30", "d", "1y", "-1d", "1.5d", "99999999999999999999d"] {
+			assert_eq!(parse_age(age), None, "{}", age);
+		}
+
+		assert_eq!(parse_size("512"), Some(512));
+		assert_eq!(parse_size("100K"), Some(100 << 10));
+		assert_eq!(parse_size("10MB"), Some(10 << 20));
+		assert_eq!(parse_size("1G"), Some(1 << 30));
+		for size in ["", "M", "10m", "1T", "-1", "99999999999G"] {
+			assert_eq!(parse_size(size), None, "{}", size);
+		}
+
+		assert_eq!(format_time(SystemTime::UNIX_EPOCH), "1970-01-01 00:00");
+		assert_eq!(
+			format_time(SystemTime::UNIX_EPOCH + Duration::from_secs(951_827_696)),
+			"2000-02-29 12:34"
+		);
+	}
+
+	#[test]
+	fn test_prune() {
+		let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+		let hour = Duration::from_secs(3600);
+		// most recent first, as Cacher::entries sorts them
+		let entries: Vec<CacheEntry> = [(1, 100), (5, 300), (30, 200), (80, 100)]
+			.into_iter()
+			.map(|(hours, size)| CacheEntry {
+				name: format!("{}h", hours),
+				size,
+				modified: now - hour * hours,
+				path: PathBuf::new(),
+			})
+			.collect();
+		let pruned = |older_than, max_size| -> Vec<String> {
+			select_pruned(entries.clone(), now, older_than, max_size)
+				.into_iter()
+				.map(|entry| entry.name)
+				.collect()
+		};
+
+		assert_eq!(pruned(None, None), [] as [&str; 0]);
+		assert_eq!(pruned(Some(hour * 24), None), ["30h", "80h"]);
+		assert_eq!(pruned(None, Some(400)), ["30h", "80h"]);
+		// an older entry that would still fit goes too
+		assert_eq!(pruned(None, Some(500)), ["30h", "80h"]);
+		assert_eq!(pruned(None, Some(50)), ["1h", "5h", "30h", "80h"]);
+		assert_eq!(pruned(Some(hour * 2), Some(1000)), ["5h", "30h", "80h"]);
+	}
+}
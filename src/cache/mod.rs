@@ -0,0 +1,586 @@
+pub mod manage;
+
+use std::{
+	cmp::Reverse,
+	env,
+	fmt::{self, LowerHex},
+	fs::{self, File},
+	io::{ErrorKind, Read},
+	path::{Path, PathBuf},
+	time::{Duration, SystemTime},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+	error::{Error, Fallible},
+	material_newtype::{
+		MateyArgb, MateyPalette, MateyPalettes, MateyScheme, MateyTheme, MateyVariant,
+	},
+	output::{WriteOptions, write_atomically},
+};
+
+use manage::select_pruned;
+
+const MAGIC: &[u8; 8] = b"MATEYTHM";
+/// Bump this whenever the layout written by `encode_theme` changes.
+const FORMAT_VERSION: u16 = 3;
+const CHECKSUM_LEN: usize = 32;
+
+pub struct Cacher {
+	cache_folder: PathBuf,
+}
+
+/// Every option that changes the theme generated from an image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThemeParams {
+	pub variant: MateyVariant,
+	pub contrast_level: f64,
+	pub source_index: usize,
+}
+
+impl ThemeParams {
+	/// The bits of the contrast level, the same for `-0.0` and `0.0`.
+	pub fn contrast_key(&self) -> u64 {
+		// adding 0.0 turns -0.0 into 0.0
+		(self.contrast_level + 0.0).to_bits()
+	}
+
+	fn key_bytes(&self) -> Vec<u8> {
+		let mut buf = Vec::new();
+		buf.extend(self.variant.name().as_bytes());
+		buf.push(0);
+		buf.extend(self.contrast_key().to_le_bytes());
+		buf.extend((self.source_index as u64).to_le_bytes());
+		buf
+	}
+}
+
+impl Cacher {
+	/// Uses the folder `name` in `$XDG_CACHE_HOME`, `$HOME/.cache` or `/tmp`,
+	/// whichever is found first.
+	pub fn new(name: impl AsRef<Path>) -> Fallible<Cacher> {
+		let mut cache_folder = if let Some(cache_home) =
+			env::var_os("XDG_CACHE_HOME").filter(|path| Path::new(path).is_absolute())
+		{
+			PathBuf::from(cache_home)
+		} else if let Some(home) = env::var_os("HOME") {
+			let mut folder = PathBuf::from(home);
+			folder.push(".cache");
+			folder
+		} else {
+			let tmp = PathBuf::from("/tmp");
+			if tmp.is_dir() {
+				tmp
+			} else {
+				return Error::io_message("cannot choose a folder for cache").into();
+			}
+		};
+
+		cache_folder.push(name);
+
+		Ok(Cacher::with_folder(cache_folder))
+	}
+
+	/// Keeps the cache directly in `cache_folder`. Nothing is created until a
+	/// theme is saved, so reading the cache works on read-only folders.
+	pub fn with_folder(cache_folder: impl Into<PathBuf>) -> Cacher {
+		Cacher {
+			cache_folder: cache_folder.into(),
+		}
+	}
+
+	/// Creates the folder if needed and fails if it can't be written to, so
+	/// a run that saves themes finds out before generating them.
+	pub fn check_writable(&self) -> Fallible<()> {
+		fs::create_dir_all(&self.cache_folder)
+			.map_err(|e| Error::io("could not create cache folder", &self.cache_folder, e))?;
+
+		let probe = self.cache_folder.join(".matey-write-test");
+		File::create(&probe)
+			.and_then(|_| fs::remove_file(&probe))
+			.map_err(|e| Error::io("could not write to cache folder", &self.cache_folder, e))
+	}
+
+	pub fn folder(&self) -> &Path {
+		&self.cache_folder
+	}
+
+	/// The entry is named after the hash of the image followed by the hash of
+	/// the parameters.
+	pub fn get(&self, raw: &[u8], params: &ThemeParams) -> CacheHandle {
+		let image_digest = Sha256::digest(raw);
+		let params_digest = Sha256::digest(params.key_bytes());
+
+		let mut path = self.cache_folder.clone();
+		path.push(format!(
+			"{:x}-{:x}",
+			HexSlice(image_digest.as_slice()),
+			HexSlice(&params_digest[..8])
+		));
+		CacheHandle {
+			path,
+			params: *params,
+		}
+	}
+
+	/// `image_path` is only stored so the entry can be told apart in
+	/// `matey cache list`, it isn't part of the key. The entry is replaced
+	/// atomically, so a matey running at the same time never reads half of it.
+	pub fn save_cache(
+		&self,
+		handle: &CacheHandle,
+		theme: &MateyTheme,
+		image_path: &Path,
+	) -> Fallible<()> {
+		write_atomically(
+			handle.as_path(),
+			&encode_theme(theme, handle.params.source_index, image_path),
+			&WriteOptions::default(),
+		)
+	}
+
+	/// Returns `None` if there is no cache for the handle, and an error if the
+	/// cache is corrupted or was written by an incompatible version of matey.
+	pub fn get_cache(&self, handle: &CacheHandle) -> Option<Fallible<MateyTheme>> {
+		if !handle.as_path().exists() {
+			return None;
+		}
+
+		Some(read_entry(handle.as_path()).and_then(|cached| {
+			let CachedTheme {
+				theme,
+				source_index,
+				..
+			} = cached;
+			if theme.variant != handle.params.variant
+				|| theme.contrast_level != handle.params.contrast_level
+				|| source_index != handle.params.source_index
+			{
+				return Err(Error::Cache {
+					path: Some(handle.as_path().to_path_buf()),
+					message: "cached theme was generated with other parameters".to_string(),
+				});
+			}
+
+			Ok(theme)
+		}))
+	}
+
+	/// Lists the entries of the cache, the most recently written first.
+	pub fn entries(&self) -> Fallible<Vec<CacheEntry>> {
+		let read_dir = match fs::read_dir(&self.cache_folder) {
+			Ok(read_dir) => read_dir,
+			// nothing was cached yet
+			Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+			Err(e) => {
+				return Err(Error::io(
+					"could not read cache folder",
+					&self.cache_folder,
+					e,
+				));
+			}
+		};
+
+		let mut entries = Vec::new();
+		for dir_entry in read_dir.flatten() {
+			let Ok(metadata) = dir_entry.metadata() else {
+				continue;
+			};
+			let Some(name) = dir_entry.file_name().to_str().map(str::to_string) else {
+				continue;
+			};
+			if !metadata.is_file() || !is_entry_name(&name) {
+				continue;
+			}
+
+			entries.push(CacheEntry {
+				name,
+				size: metadata.len(),
+				modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+				path: dir_entry.path(),
+			});
+		}
+
+		entries.sort_by_key(|entry| Reverse(entry.modified));
+		Ok(entries)
+	}
+
+	/// Returns the entries whose name or short name starts with `prefix`.
+	pub fn find(&self, prefix: &str) -> Fallible<Vec<CacheEntry>> {
+		let mut entries = self.entries()?;
+		entries.retain(|entry| {
+			entry.name.starts_with(prefix) || entry.short_name().starts_with(prefix)
+		});
+		Ok(entries)
+	}
+
+	pub fn load(&self, entry: &CacheEntry) -> Fallible<CachedTheme> {
+		read_entry(&entry.path)
+	}
+
+	pub fn remove(&self, entry: &CacheEntry) -> Fallible<()> {
+		fs::remove_file(&entry.path).map_err(|e| Error::io("could not remove", &entry.path, e))
+	}
+
+	/// Removes the entries older than `older_than`, then the oldest ones
+	/// until the cache takes at most `max_size` bytes. Returns what was
+	/// removed.
+	pub fn prune(
+		&self,
+		older_than: Option<Duration>,
+		max_size: Option<u64>,
+	) -> Fallible<Vec<CacheEntry>> {
+		let removed = select_pruned(self.entries()?, SystemTime::now(), older_than, max_size);
+		for entry in &removed {
+			self.remove(entry)?;
+		}
+
+		Ok(removed)
+	}
+
+	/// Removes every entry and returns how many there were.
+	pub fn clear(&self) -> Fallible<usize> {
+		let entries = self.entries()?;
+		for entry in &entries {
+			self.remove(entry)?;
+		}
+
+		Ok(entries.len())
+	}
+}
+
+/// A file in the cache folder, see [`Cacher::entries`].
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+	pub name: String,
+	pub size: u64,
+	pub modified: SystemTime,
+	path: PathBuf,
+}
+
+impl CacheEntry {
+	/// The start of both hashes, enough to tell entries apart.
+	pub fn short_name(&self) -> String {
+		match self.name.split_once('-') {
+			Some((image, params)) => format!("{}-{}", &image[..8], &params[..8]),
+			None => self.name[..8].to_string(),
+		}
+	}
+}
+
+/// What is stored in a cache entry.
+#[derive(Debug, Clone)]
+pub struct CachedTheme {
+	pub theme: MateyTheme,
+	/// Which of the image's candidates the theme was generated from.
+	pub source_index: usize,
+	pub image_path: PathBuf,
+}
+
+/// Entries are named `{64 hex digits}-{16 hex digits}`, anything else in the
+/// folder isn't ours. Older versions of matey named them after the image
+/// only, writing each byte of the hash without padding, so those names are
+/// 32 to 64 hex digits long. They are kept so they can be pruned.
+fn is_entry_name(name: &str) -> bool {
+	let is_hex = |s: &str| s.chars().all(|c| c.is_ascii_hexdigit());
+	match name.split_once('-') {
+		Some((image, params)) => {
+			image.len() == 64 && params.len() == 16 && is_hex(image) && is_hex(params)
+		}
+		None => (32..=64).contains(&name.len()) && is_hex(name),
+	}
+}
+
+fn read_entry(path: &Path) -> Fallible<CachedTheme> {
+	let mut cache = Vec::new();
+	File::open(path)
+		.and_then(|mut file| file.read_to_end(&mut cache))
+		.map_err(|e| Error::io("could not read cache entry", path, e))?;
+
+	decode_theme(&cache).map_err(|e| e.with_path(path))
+}
+
+#[derive(Debug, Clone)]
+pub struct CacheHandle {
+	path: PathBuf,
+	params: ThemeParams,
+}
+
+impl CacheHandle {
+	fn as_path(&self) -> &PathBuf {
+		&self.path
+	}
+}
+
+struct HexSlice<'a>(&'a [u8]);
+
+impl<'a> LowerHex for HexSlice<'a> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for b in self.0 {
+			write!(f, "{:02x}", b)?;
+		}
+
+		Ok(())
+	}
+}
+
+/// Layout, all numbers being little endian:
+/// - the magic number and the format version (u16)
+/// - the generation parameters: the source color, the variant's name
+///   (prefixed by its length as a u8), the contrast level (f64) and the
+///   source index (u32)
+/// - the path of the image, as UTF-8 prefixed by its length as a u16
+/// - the number of colors in a scheme (u16) followed by the light and dark
+///   schemes
+/// - the number of palettes (u8), the number of tones in a palette (u16) and
+///   the palettes, in the order of [`MateyPalettes::NAMES`]
+/// - the SHA-256 of everything above
+fn encode_theme(theme: &MateyTheme, source_index: usize, image_path: &Path) -> Vec<u8> {
+	let push_color = |buf: &mut Vec<u8>, color: &MateyArgb| {
+		buf.extend([color.alpha, color.red, color.green, color.blue])
+	};
+
+	let mut buf = Vec::new();
+	buf.extend(MAGIC);
+	buf.extend(FORMAT_VERSION.to_le_bytes());
+
+	push_color(&mut buf, &theme.source);
+	let variant = theme.variant.name();
+	buf.push(variant.len() as u8);
+	buf.extend(variant.as_bytes());
+	buf.extend(theme.contrast_level.to_le_bytes());
+	buf.extend((source_index as u32).to_le_bytes());
+
+	let image_path = image_path.to_string_lossy();
+	let image_path = &image_path.as_bytes()[..image_path.len().min(u16::MAX as usize)];
+	buf.extend((image_path.len() as u16).to_le_bytes());
+	buf.extend(image_path);
+
+	buf.extend((MateyScheme::FIELD_COUNT as u16).to_le_bytes());
+	for scheme in [&theme.light, &theme.dark] {
+		for (_, color) in scheme {
+			push_color(&mut buf, color);
+		}
+	}
+
+	buf.push(MateyPalettes::NAMES.len() as u8);
+	buf.extend((MateyPalette::TONE_COUNT as u16).to_le_bytes());
+	for (_, palette) in &theme.palettes {
+		for (_, color) in palette.iter() {
+			push_color(&mut buf, &color);
+		}
+	}
+
+	let checksum = Sha256::digest(&buf);
+	buf.extend(checksum);
+	buf
+}
+
+fn decode_theme(buf: &[u8]) -> Fallible<CachedTheme> {
+	let malformed = || Error::cache("malformed cache entry");
+
+	if buf.len() < MAGIC.len() + 2 + CHECKSUM_LEN || !buf.starts_with(MAGIC) {
+		return Err(malformed());
+	}
+
+	let (content, checksum) = buf.split_at(buf.len() - CHECKSUM_LEN);
+	if Sha256::digest(content).as_slice() != checksum {
+		return Err(Error::cache("cache entry checksum doesn't match"));
+	}
+
+	let mut reader = Reader(&content[MAGIC.len()..]);
+
+	let version = reader.u16()?;
+	if version != FORMAT_VERSION {
+		return Err(Error::cache(format!(
+			"cache format version {} is not supported",
+			version
+		)));
+	}
+
+	let source = reader.color()?;
+	let variant_len = reader.take(1)?[0] as usize;
+	let variant = str::from_utf8(reader.take(variant_len)?)
+		.ok()
+		.and_then(MateyVariant::from_name)
+		.ok_or_else(malformed)?;
+	let contrast_level = f64::from_le_bytes(reader.array()?);
+	let source_index = u32::from_le_bytes(reader.array()?) as usize;
+
+	let image_path_len = reader.u16()? as usize;
+	let image_path =
+		PathBuf::from(str::from_utf8(reader.take(image_path_len)?).map_err(|_| malformed())?);
+
+	if reader.u16()? as usize != MateyScheme::FIELD_COUNT {
+		return Err(malformed());
+	}
+	let mut scheme = || {
+		let colors = (0..MateyScheme::FIELD_COUNT)
+			.map(|_| reader.color())
+			.collect::<Fallible<Vec<_>>>()?;
+		MateyScheme::from_fields(colors.into_iter()).ok_or_else(malformed)
+	};
+	let light = scheme()?;
+	let dark = scheme()?;
+
+	if reader.take(1)?[0] as usize != MateyPalettes::NAMES.len()
+		|| reader.u16()? as usize != MateyPalette::TONE_COUNT
+	{
+		return Err(malformed());
+	}
+	let mut palette = || -> Fallible<MateyPalette> {
+		let mut tones = [MateyArgb::default(); MateyPalette::TONE_COUNT];
+		for tone in &mut tones {
+			*tone = reader.color()?;
+		}
+		Ok(MateyPalette::from_tones(tones))
+	};
+	let palettes = MateyPalettes {
+		primary: palette()?,
+		secondary: palette()?,
+		tertiary: palette()?,
+		neutral: palette()?,
+		neutral_variant: palette()?,
+		error: palette()?,
+	};
+
+	if !reader.0.is_empty() {
+		return Err(malformed());
+	}
+
+	Ok(CachedTheme {
+		theme: MateyTheme::new(source, light, dark, palettes, variant, contrast_level),
+		source_index,
+		image_path,
+	})
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+	fn take(&mut self, len: usize) -> Fallible<&'a [u8]> {
+		if self.0.len() < len {
+			return Err(Error::cache("malformed cache entry"));
+		}
+
+		let (taken, rest) = self.0.split_at(len);
+		self.0 = rest;
+		Ok(taken)
+	}
+
+	fn array<const N: usize>(&mut self) -> Fallible<[u8; N]> {
+		Ok(self.take(N)?.try_into().unwrap())
+	}
+
+	fn u16(&mut self) -> Fallible<u16> {
+		Ok(u16::from_le_bytes(self.array()?))
+	}
+
+	fn color(&mut self) -> Fallible<MateyArgb> {
+		let [alpha, red, green, blue] = self.array()?;
+		Ok(MateyArgb {
+			alpha,
+			red,
+			green,
+			blue,
+		})
+	}
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use crate::{
+		cache::{Cacher, ThemeParams, decode_theme, encode_theme, is_entry_name},
+		material_newtype::{MateyTheme, MateyVariant},
+		test_util::{TempFolder, test_source, test_theme},
+	};
+	#[allow(unused_imports)]
+	use std::{fs, path::Path};
+
+	#[test]
+	fn test_cache_format() {
+		let theme = MateyTheme::from_source(test_source(), MateyVariant::Vibrant, 0.5);
+		let image_path = Path::new("/home/user/wallpapers/forest.png");
+		let mut encoded = encode_theme(&theme, 3, image_path);
+
+		let decoded = decode_theme(&encoded).unwrap();
+		assert_eq!(
+			encode_theme(&decoded.theme, decoded.source_index, &decoded.image_path),
+			encoded
+		);
+		assert_eq!(decoded.theme.variant, MateyVariant::Vibrant);
+		assert_eq!(decoded.source_index, 3);
+		assert_eq!(decoded.image_path, image_path);
+
+		encoded[20] ^= 1;
+		assert!(decode_theme(&encoded).is_err());
+		assert!(decode_theme(&encoded[..40]).is_err());
+
+		assert!(is_entry_name(&format!(
+			"{}-{}",
+			"0a".repeat(32),
+			"ff".repeat(8)
+		)));
+		assert!(is_entry_name(&"a".repeat(45)));
+		assert!(!is_entry_name(&"a".repeat(31)));
+		assert!(!is_entry_name(&format!(
+			"{}-{}",
+			"a".repeat(45),
+			"ff".repeat(8)
+		)));
+		assert!(!is_entry_name("notes.txt"));
+		assert!(!is_entry_name(".matey-write-test"));
+	}
+
+	#[test]
+	fn test_cache_params() {
+		let folder = TempFolder::new("cache");
+		let cacher = Cacher::with_folder(folder.to_path_buf());
+		// reading doesn't create the folder
+		assert!(cacher.entries().unwrap().is_empty());
+		assert!(!folder.exists());
+		cacher.check_writable().unwrap();
+		assert!(folder.is_dir());
+		let theme = test_theme();
+		let params = ThemeParams {
+			variant: MateyVariant::TonalSpot,
+			contrast_level: 0.0,
+			source_index: 0,
+		};
+		let saved = cacher.get(b"image", &params);
+		cacher
+			.save_cache(&saved, &theme, Path::new("image.png"))
+			.unwrap();
+		assert!(cacher.get_cache(&saved).unwrap().is_ok());
+
+		// an entry found under the key of other parameters is rejected
+		for other in [
+			ThemeParams {
+				source_index: 1,
+				..params
+			},
+			ThemeParams {
+				contrast_level: 0.5,
+				..params
+			},
+			ThemeParams {
+				variant: MateyVariant::Vibrant,
+				..params
+			},
+		] {
+			let handle = cacher.get(b"image", &other);
+			assert!(cacher.get_cache(&handle).is_none());
+			fs::copy(saved.as_path(), handle.as_path()).unwrap();
+			assert!(cacher.get_cache(&handle).unwrap().is_err());
+		}
+
+		// -0.0 is the same theme as 0.0, in memory and on disk
+		let negative = ThemeParams {
+			contrast_level: -0.0,
+			..params
+		};
+		assert_eq!(negative.contrast_key(), params.contrast_key());
+		assert_eq!(cacher.get(b"image", &negative).as_path(), saved.as_path());
+	}
+}
@@ -0,0 +1,153 @@
+use std::{
+	fmt,
+	path::{Path, PathBuf},
+};
+
+/// A range of bytes in the source of a template file.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct Span {
+	pub start: usize,
+	pub end: usize,
+}
+
+impl Span {
+	pub fn new(start: usize, end: usize) -> Self {
+		Span { start, end }
+	}
+}
+
+/// A line and a column, both starting at 1. Columns count characters, not
+/// bytes.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct FileLocation {
+	pub line: usize,
+	pub column: usize,
+}
+
+impl FileLocation {
+	pub fn of(source: &str, offset: usize) -> Self {
+		let before = &source[..floor_char_boundary(source, offset)];
+		let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+
+		FileLocation {
+			line: before.matches('\n').count() + 1,
+			column: before[line_start..].chars().count() + 1,
+		}
+	}
+}
+
+impl fmt::Display for FileLocation {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}:{}", self.line, self.column)
+	}
+}
+
+fn floor_char_boundary(source: &str, offset: usize) -> usize {
+	let mut offset = offset.min(source.len());
+	while !source.is_char_boundary(offset) {
+		offset -= 1;
+	}
+	offset
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+	Error,
+	Warning,
+}
+
+/// A problem in a template file, printed the way rustc does:
+///
+/// ```text
+/// error: unknown command outfile
+///  --> kitty.conf:1:2
+///   |
+/// 1 | #outfile "kitty.conf"
+///   |  ^^^^^^^
+///   = help: the commands are out, naming, variant, ...
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+	pub severity: Severity,
+	pub message: String,
+	pub path: PathBuf,
+	pub span: Span,
+	pub location: FileLocation,
+	/// The line the span starts on.
+	pub line: String,
+	/// How many characters of `line` the span covers, at least 1.
+	pub width: usize,
+	pub hint: Option<String>,
+}
+
+impl Diagnostic {
+	pub fn new(
+		severity: Severity,
+		path: &Path,
+		source: &str,
+		span: Span,
+		message: impl Into<String>,
+	) -> Self {
+		let start = floor_char_boundary(source, span.start);
+		let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+		let line_end = source[start..]
+			.find('\n')
+			.map_or(source.len(), |i| start + i);
+		let end = floor_char_boundary(source, span.end).clamp(start, line_end);
+
+		Diagnostic {
+			severity,
+			message: message.into(),
+			path: path.to_path_buf(),
+			span,
+			location: FileLocation::of(source, start),
+			line: source[line_start..line_end]
+				.trim_end_matches('\r')
+				.to_string(),
+			width: source[start..end].chars().count().max(1),
+			hint: None,
+		}
+	}
+
+	pub fn error(path: &Path, source: &str, span: Span, message: impl Into<String>) -> Self {
+		Diagnostic::new(Severity::Error, path, source, span, message)
+	}
+
+	pub fn warning(path: &Path, source: &str, span: Span, message: impl Into<String>) -> Self {
+		Diagnostic::new(Severity::Warning, path, source, span, message)
+	}
+
+	pub fn with_hint(mut self, hint: impl Into<String>) -> Self {
+		self.hint = Some(hint.into());
+		self
+	}
+}
+
+impl fmt::Display for Diagnostic {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let severity = match self.severity {
+			Severity::Error => "error",
+			Severity::Warning => "warning",
+		};
+		let line_number = self.location.line.to_string();
+		let gutter = " ".repeat(line_number.len());
+		// tabs are kept so the carets line up with the line above
+		let indent: String = self
+			.line
+			.chars()
+			.take(self.location.column - 1)
+			.map(|c| if c == '\t' { '\t' } else { ' ' })
+			.collect();
+
+		writeln!(f, "{}: {}", severity, self.message)?;
+		writeln!(f, "{}--> {}:{}", gutter, self.path.display(), self.location)?;
+		writeln!(f, "{} |", gutter)?;
+		writeln!(f, "{} | {}", line_number, self.line)?;
+		write!(f, "{} | {}{}", gutter, indent, "^".repeat(self.width))?;
+		if let Some(hint) = &self.hint {
+			write!(f, "\n{} = help: {}", gutter, hint)?;
+		}
+
+		Ok(())
+	}
+}
@@ -2,47 +2,274 @@ use std::{
 	error,
 	fmt::{self},
 	io,
+	path::{Path, PathBuf},
+	process,
 };
 
+use crate::diagnostic::Diagnostic;
+
 pub type Fallible<T> = Result<T, Error>;
 
-#[derive(Debug, Clone)]
+/// Everything that can go wrong in matey, with what it went wrong on.
+/// [`Error::exit_code`] tells which [`ExitCode`] it ends matey with.
+#[derive(Debug)]
 pub enum Error {
-	Parse(String),
-	Tokenizing(String),
-	IO(String),
-	Config(String),
+	/// The command line doesn't make sense.
+	Usage(String),
+	/// An image that couldn't be read or decoded.
+	Image {
+		path: Option<PathBuf>,
+		source: Box<dyn error::Error + Send + Sync>,
+	},
+	/// Every error and warning found in a template file, each with its path
+	/// and span.
+	Parse(Vec<Diagnostic>),
+	/// A template that parsed but can't be used as it is.
+	Config {
+		path: Option<PathBuf>,
+		message: String,
+	},
+	/// A file or process operation that failed. `message` says what was being
+	/// done, as in "could not write".
+	IO {
+		message: String,
+		path: Option<PathBuf>,
+		source: Option<io::Error>,
+	},
+	/// A cache entry that can't be used.
+	Cache {
+		path: Option<PathBuf>,
+		message: String,
+	},
+	/// Anything the variants above don't cover, such as the logger failing
+	/// to start.
+	Other(String),
 }
 
 impl Error {
 	pub fn from_io(error: io::Error) -> Self {
-		Error::IO(error.to_string())
+		Error::IO {
+			message: "IO error".to_string(),
+			path: None,
+			source: Some(error),
+		}
 	}
 
-	pub fn parse_error(message: String) -> Self {
-		Error::Parse(message)
+	/// An IO error about `path`.
+	pub fn io(message: impl Into<String>, path: impl AsRef<Path>, source: io::Error) -> Self {
+		Error::IO {
+			message: message.into(),
+			path: Some(path.as_ref().to_path_buf()),
+			source: Some(source),
+		}
 	}
 
-	pub fn tokenizing_error(message: String) -> Self {
-		Error::Tokenizing(message)
+	/// Failures that have no [`io::Error`] behind them, such as a hook
+	/// exiting with an error.
+	pub fn io_message(message: impl Into<String>) -> Self {
+		Error::IO {
+			message: message.into(),
+			path: None,
+			source: None,
+		}
+	}
+
+	pub fn config(message: impl Into<String>) -> Self {
+		Error::Config {
+			path: None,
+			message: message.into(),
+		}
+	}
+
+	pub fn cache(message: impl Into<String>) -> Self {
+		Error::Cache {
+			path: None,
+			message: message.into(),
+		}
+	}
+
+	/// Sets the path the error is about, for errors found where it isn't
+	/// known. Paths that are already set are kept.
+	pub fn with_path(mut self, new_path: impl AsRef<Path>) -> Self {
+		match &mut self {
+			Error::Image { path, .. }
+			| Error::Config { path, .. }
+			| Error::IO { path, .. }
+			| Error::Cache { path, .. } => {
+				path.get_or_insert_with(|| new_path.as_ref().to_path_buf());
+			}
+			Error::Usage(_) | Error::Parse(_) | Error::Other(_) => {}
+		}
+		self
+	}
+
+	pub fn exit_code(&self) -> ExitCode {
+		match self {
+			Error::Usage(_) => ExitCode::Usage,
+			Error::Image { .. } => ExitCode::Image,
+			Error::Parse(_) | Error::Config { .. } => ExitCode::Template,
+			Error::IO { .. } | Error::Cache { .. } => ExitCode::IO,
+			Error::Other(_) => ExitCode::Failure,
+		}
 	}
 }
 
 impl fmt::Display for Error {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let with_path =
+			|f: &mut fmt::Formatter<'_>, message: &str, path: &Option<PathBuf>| match path {
+				Some(path) => write!(f, "{} ({})", message, path.display()),
+				None => write!(f, "{}", message),
+			};
+
 		match self {
-			Error::Parse(message) => write!(f, "Parsing Error: {}", message),
-			Error::Tokenizing(message) => write!(f, "Tokenizing Error: {}", message),
-			Error::IO(message) => write!(f, "IO Error: {}", message),
-			Error::Config(message) => write!(f, "Config Error: {}", message),
+			Error::Usage(message) => write!(f, "{}", message),
+			Error::Image { path, source } => match path {
+				Some(path) => write!(f, "could not read image {}: {}", path.display(), source),
+				None => write!(f, "could not read image: {}", source),
+			},
+			Error::Parse(diagnostics) => {
+				for (i, diagnostic) in diagnostics.iter().enumerate() {
+					if i > 0 {
+						write!(f, "\n\n")?;
+					}
+					write!(f, "{}", diagnostic)?;
+				}
+				Ok(())
+			}
+			Error::Config { path, message } => with_path(f, message, path),
+			Error::IO {
+				message,
+				path,
+				source,
+			} => {
+				write!(f, "{}", message)?;
+				if let Some(path) = path {
+					write!(f, " {}", path.display())?;
+				}
+				if let Some(source) = source {
+					write!(f, ": {}", source)?;
+				}
+				Ok(())
+			}
+			Error::Cache { path, message } => with_path(f, message, path),
+			Error::Other(message) => write!(f, "{}", message),
 		}
 	}
 }
 
-impl error::Error for Error {}
+impl error::Error for Error {
+	fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+		match self {
+			Error::Image { source, .. } => Some(source.as_ref()),
+			Error::IO {
+				source: Some(source),
+				..
+			} => Some(source),
+			_ => None,
+		}
+	}
+}
 
 impl<T> From<Error> for Result<T, Error> {
 	fn from(value: Error) -> Self {
 		Err(value)
 	}
 }
+
+/// The codes matey exits with. Scripts can rely on them, they don't change
+/// between versions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitCode {
+	Success = 0,
+	/// Something went wrong that isn't covered below.
+	Failure = 1,
+	/// Unknown options, missing or invalid values.
+	Usage = 2,
+	/// The image couldn't be read or decoded.
+	Image = 3,
+	/// A template has errors, or `matey check` found problems.
+	Template = 4,
+	/// Files couldn't be read or written, including the cache.
+	IO = 5,
+}
+
+impl ExitCode {
+	pub const ALL: [ExitCode; 6] = [
+		ExitCode::Success,
+		ExitCode::Failure,
+		ExitCode::Usage,
+		ExitCode::Image,
+		ExitCode::Template,
+		ExitCode::IO,
+	];
+
+	/// A short description for `--help`.
+	pub fn description(&self) -> &'static str {
+		match self {
+			ExitCode::Success => "success",
+			ExitCode::Failure => "unexpected failure",
+			ExitCode::Usage => "invalid command line",
+			ExitCode::Image => "the image couldn't be read",
+			ExitCode::Template => "a template has errors",
+			ExitCode::IO => "a file couldn't be read or written",
+		}
+	}
+}
+
+impl From<ExitCode> for process::ExitCode {
+	fn from(code: ExitCode) -> Self {
+		process::ExitCode::from(code as u8)
+	}
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use crate::error::{Error, ExitCode};
+	#[allow(unused_imports)]
+	use std::{
+		error::Error as _,
+		io::{self, ErrorKind},
+	};
+
+	#[test]
+	fn test_exit_codes() {
+		let codes = [
+			(Error::Usage("bad".to_string()), ExitCode::Usage),
+			(
+				Error::Image {
+					path: None,
+					source: "bad".into(),
+				},
+				ExitCode::Image,
+			),
+			(Error::Parse(Vec::new()), ExitCode::Template),
+			(Error::config("bad"), ExitCode::Template),
+			(Error::io_message("bad"), ExitCode::IO),
+			(Error::cache("bad"), ExitCode::IO),
+			(Error::Other("bad".to_string()), ExitCode::Failure),
+		];
+		for (error, code) in codes {
+			assert_eq!(error.exit_code(), code, "{:?}", error);
+		}
+
+		// scripts rely on the numbers
+		let numbers = ExitCode::ALL.map(|code| code as u8);
+		assert_eq!(numbers, [0, 1, 2, 3, 4, 5]);
+	}
+
+	#[test]
+	fn test_source() {
+		let error = Error::io(
+			"could not read",
+			"out.conf",
+			io::Error::new(ErrorKind::NotFound, "gone"),
+		);
+		let source = error.source().unwrap().downcast_ref::<io::Error>().unwrap();
+		assert_eq!(source.kind(), ErrorKind::NotFound);
+		assert_eq!(error.to_string(), "could not read out.conf: gone");
+
+		assert!(Error::io_message("hook failed").source().is_none());
+		assert!(Error::Usage("bad".to_string()).source().is_none());
+	}
+}
@@ -0,0 +1,122 @@
+use std::{
+	io::{BufRead, BufReader, Read},
+	path::Path,
+	process::{Command, Stdio},
+	thread,
+	time::{Duration, Instant},
+};
+
+use log::info;
+
+use crate::error::{Error, Fallible};
+
+/// How long a `#hook` may run before it gets killed. Hooks that need longer
+/// can start their work in the background with `&`.
+pub const HOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Runs `command` with `sh -c`, logging what it prints. `output` is the file
+/// that was just written, hooks get it in `$MATEY_OUTPUT`.
+pub fn run_hook(command: &str, output: &Path, timeout: Duration) -> Fallible<()> {
+	info!("running hook {:?}", command);
+
+	let mut child = Command::new("sh")
+		.arg("-c")
+		.arg(command)
+		.env("MATEY_OUTPUT", output)
+		.stdin(Stdio::null())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.spawn()
+		.map_err(|e| Error::IO {
+			message: format!("could not run hook {:?}", command),
+			path: None,
+			source: Some(e),
+		})?;
+
+	// the pipes are drained on their own threads so a chatty hook can't block
+	let loggers = [
+		child.stdout.take().map(|out| log_lines(command, out)),
+		child.stderr.take().map(|err| log_lines(command, err)),
+	];
+
+	let deadline = Instant::now() + timeout;
+	let status = loop {
+		match child.try_wait().map_err(Error::from_io)? {
+			Some(status) => break Some(status),
+			None if Instant::now() >= deadline => {
+				let _ = child.kill();
+				let _ = child.wait();
+				break None;
+			}
+			None => thread::sleep(Duration::from_millis(20)),
+		}
+	};
+
+	// processes the hook started in the background keep the pipes open, so
+	// the output is only waited for a little while
+	let grace = Instant::now() + Duration::from_millis(200);
+	while loggers.iter().flatten().any(|logger| !logger.is_finished()) && Instant::now() < grace {
+		thread::sleep(Duration::from_millis(20));
+	}
+
+	match status {
+		Some(status) if status.success() => Ok(()),
+		Some(status) => Err(Error::io_message(format!(
+			"hook {:?} failed with {}",
+			command, status
+		))),
+		None => Err(Error::io_message(format!(
+			"hook {:?} timed out after {}s",
+			command,
+			timeout.as_secs_f64()
+		))),
+	}
+}
+
+fn log_lines(command: &str, pipe: impl Read + Send + 'static) -> thread::JoinHandle<()> {
+	let command = command.to_string();
+	thread::spawn(move || {
+		for line in BufReader::new(pipe).lines().map_while(Result::ok) {
+			info!("[{}] {}", command, line);
+		}
+	})
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use crate::hook::run_hook;
+	#[allow(unused_imports)]
+	use std::{
+		path::Path,
+		time::{Duration, Instant},
+	};
+
+	#[test]
+	fn test_run_hook() {
+		let output = Path::new("/tmp/matey test/out.conf");
+		let second = Duration::from_secs(1);
+
+		assert!(run_hook("true", output, second).is_ok());
+		let failed = run_hook("exit 3", output, second).unwrap_err();
+		assert!(failed.to_string().contains("exit status: 3"), "{}", failed);
+
+		assert!(
+			run_hook(
+				"test \"$MATEY_OUTPUT\" = '/tmp/matey test/out.conf'",
+				output,
+				second
+			)
+			.is_ok()
+		);
+		assert!(run_hook("test \"$MATEY_OUTPUT\" = /tmp/other", output, second).is_err());
+
+		let start = Instant::now();
+		let timed_out = run_hook("sleep 5", output, Duration::from_millis(100)).unwrap_err();
+		assert!(start.elapsed() < Duration::from_secs(2));
+		assert!(
+			timed_out.to_string().contains("timed out after 0.1s"),
+			"{}",
+			timed_out
+		);
+	}
+}
@@ -2,6 +2,13 @@
 
 pub mod args;
 pub mod cache;
-pub(crate) mod error;
+pub mod diagnostic;
+pub mod error;
+pub mod hook;
 pub mod material_newtype;
+pub mod output;
 pub mod parsers;
+pub mod source;
+pub mod watch;
+
+mod test_util;
@@ -1,55 +1,151 @@
 #![allow(clippy::uninlined_format_args)]
 
-use std::error::Error;
+use std::collections::HashMap;
 use std::fs;
-use std::path::{PathBuf, absolute};
-use std::{env, fs::File, io::Read};
+use std::path::{Path, PathBuf, absolute};
+use std::process;
+use std::time::Duration;
+use std::{env, fs::File, io::Read, iter};
 
-use log::{LevelFilter, error, info};
-use material_colors::{image::ImageReader, theme::ThemeBuilder};
+use log::{Level, LevelFilter, error, info, log_enabled, warn};
+use material_colors::color::Argb;
 
 use matey::args::{Arg, ArgParser, ArgParserBuilder, ArgType};
-use matey::cache::Cacher;
-use matey::material_newtype::MateyTheme;
+use matey::cache::manage::{format_time, parse_age, parse_size};
+use matey::cache::{CacheEntry, Cacher, ThemeParams};
+use matey::diagnostic::Severity;
+use matey::error::{Error, ExitCode, Fallible};
+use matey::material_newtype::{MateyArgb, MateyTheme, MateyVariant};
 use matey::parsers::IndexableVariable;
-use matey::parsers::parse_config;
+use matey::parsers::{check_config, parse_config};
+use matey::source::{extract_candidates, select_source};
+use matey::watch::{Watcher, file_changed};
 
 use simple_logger::SimpleLogger;
 
-fn try_load_from_config(template_files: &mut Vec<PathBuf>) -> Result<PathBuf, Box<dyn Error>> {
+fn try_load_from_config(template_files: &mut Vec<PathBuf>) -> Fallible<PathBuf> {
 	let mut config_path = PathBuf::new();
 
 	if let Ok(path) = env::var("XDG_CONFIG_HOME") {
 		config_path.push(path);
-	} else {
-		config_path.push(env::var("HOME")?);
+	} else if let Some(home) = env::var_os("HOME") {
+		config_path.push(home);
 		config_path.push(".config");
+	} else {
+		return Err(Error::io_message("neither XDG_CONFIG_HOME nor HOME is set"));
 	}
 
 	config_path.push("matey");
-	fs::create_dir_all(&config_path)?;
+	fs::create_dir_all(&config_path)
+		.map_err(|e| Error::io("could not create config folder", &config_path, e))?;
 
-	for entry in fs::read_dir(&config_path)? {
-		template_files.push(entry?.path());
+	let read_error = |e| Error::io("could not read config folder", &config_path, e);
+	for entry in fs::read_dir(&config_path).map_err(read_error)? {
+		template_files.push(entry.map_err(read_error)?.path());
 	}
 
 	Ok(config_path)
 }
 
-fn compute_theme(buffer: &[u8]) -> MateyTheme {
-	let mut image = ImageReader::read(buffer).expect("Could not parse image");
-	image.resize(128, 128, material_colors::image::FilterType::Lanczos3);
-	let theme = ThemeBuilder::with_source(ImageReader::extract_color(&image)).build();
-	MateyTheme::new(theme.schemes.light.into(), theme.schemes.dark.into())
+struct ThemeCache {
+	image: Option<(PathBuf, Vec<u8>)>,
+	cacher: Option<Cacher>,
+	source_index: usize,
+	source: Option<Argb>,
+	/// Keyed on the variant and [`ThemeParams::contrast_key`].
+	themes: HashMap<(MateyVariant, u64), MateyTheme>,
+}
+
+impl ThemeCache {
+	fn from_image(
+		path: PathBuf,
+		buffer: Vec<u8>,
+		cacher: Option<Cacher>,
+		source_index: usize,
+	) -> Self {
+		ThemeCache {
+			image: Some((path, buffer)),
+			cacher,
+			source_index,
+			source: None,
+			themes: HashMap::new(),
+		}
+	}
+
+	fn from_color(color: Argb) -> Self {
+		ThemeCache {
+			image: None,
+			cacher: None,
+			source_index: 0,
+			source: Some(color),
+			themes: HashMap::new(),
+		}
+	}
+
+	fn compute_theme(
+		&mut self,
+		variant: MateyVariant,
+		contrast_level: f64,
+	) -> Fallible<MateyTheme> {
+		let source = match (self.source, &self.image) {
+			(Some(source), _) => source,
+			(None, Some((path, buffer))) => *self.source.insert(
+				select_source(buffer, self.source_index)
+					.map_err(|e| e.with_path(path))?
+					.into(),
+			),
+			(None, None) => unreachable!(),
+		};
+		Ok(MateyTheme::from_source(source, variant, contrast_level))
+	}
+
+	/// Only fails if the image has to be decoded and can't be.
+	fn get(&mut self, variant: MateyVariant, contrast_level: f64) -> Fallible<&MateyTheme> {
+		let params = ThemeParams {
+			variant,
+			contrast_level,
+			source_index: self.source_index,
+		};
+		let key = (variant, params.contrast_key());
+		if !self.themes.contains_key(&key) {
+			let theme = if let (Some(cacher), Some((_, buffer))) = (&self.cacher, &self.image) {
+				let handle = cacher.get(buffer, &params);
+				match cacher.get_cache(&handle) {
+					Some(Ok(theme)) => theme,
+					cached => {
+						if let Some(Err(e)) = cached {
+							warn!("regenerating cached theme: {}", e);
+						}
+
+						let theme = self.compute_theme(variant, contrast_level)?;
+
+						if let (Some(cacher), Some((path, _))) = (&self.cacher, &self.image)
+							&& cacher.save_cache(&handle, &theme, path).is_err()
+						{
+							error!("could not save theme to cache");
+						}
+
+						theme
+					}
+				}
+			} else {
+				self.compute_theme(variant, contrast_level)?
+			};
+
+			self.themes.insert(key, theme);
+		}
+
+		Ok(&self.themes[&key])
+	}
 }
 
-fn build_arg_parser() -> ArgParser {
+fn build_arg_parser(args: impl IntoIterator<Item = String>) -> ArgParserBuilder {
 	ArgParserBuilder::new(
-		env::args(),
+		args,
 		Arg::new(
 			"image",
 			Some("-i"),
-			None,
+			Some("--image"),
 			"the image to use",
 			ArgType::String,
 		),
@@ -57,10 +153,38 @@ fn build_arg_parser() -> ArgParser {
 	.add_opt(Arg::new(
 		"template",
 		Some("-t"),
-		None,
+		Some("--template"),
 		"an additional template",
 		ArgType::String,
 	))
+	.add_opt(Arg::new(
+		"color",
+		None,
+		Some("--color"),
+		"generate the theme from a seed color (#RRGGBB) instead of an image",
+		ArgType::String,
+	))
+	.add_opt(Arg::new(
+		"source-index",
+		Some("-s"),
+		Some("--source-index"),
+		"which of the colors listed by `matey candidates` to use, defaults to 0",
+		ArgType::String,
+	))
+	.add_opt(Arg::new(
+		"variant",
+		None,
+		Some("--variant"),
+		"the scheme variant (tonal_spot, vibrant, expressive, fidelity, content, ...)",
+		ArgType::String,
+	))
+	.add_opt(Arg::new(
+		"contrast",
+		Some("-c"),
+		Some("--contrast"),
+		"the contrast level, from -1.0 (reduced) to 1.0 (high), defaults to 0.0",
+		ArgType::String,
+	))
 	.add_opt(Arg::new(
 		"use-cache",
 		Some("-u"),
@@ -68,6 +192,7 @@ fn build_arg_parser() -> ArgParser {
 		"whether matey should use the cache",
 		ArgType::Flag,
 	))
+	.add_opt(cache_dir_opt())
 	.add_opt(Arg::new(
 		"light",
 		Some("-l"),
@@ -86,7 +211,7 @@ fn build_arg_parser() -> ArgParser {
 		"dry-run",
 		Some("-d"),
 		Some("--dry-run"),
-		"don't write any configs, only check for syntax errors and generate theme",
+		"don't write configs or run hooks, only print the configs that would change",
 		ArgType::Flag,
 	))
 	.add_opt(Arg::new(
@@ -110,39 +235,364 @@ fn build_arg_parser() -> ArgParser {
 		"prints version",
 		ArgType::Flag,
 	))
-	.add_priority_opt(Arg::new(
+	.add_priority_opt(help_opt())
+}
+
+fn print_swatch(color: &MateyArgb) {
+	print!(
+		"\x1b[48;2;{};{};{}m      \x1b[0m",
+		color.red, color.green, color.blue
+	);
+}
+
+fn help_opt() -> Arg {
+	Arg::new(
 		"help",
 		Some("-h"),
 		Some("--help"),
 		"print help text",
 		ArgType::Flag,
-	))
+	)
+}
+
+fn cache_dir_opt() -> Arg {
+	Arg::new(
+		"cache-dir",
+		None,
+		Some("--cache-dir"),
+		"keep the cache in DIR instead of $XDG_CACHE_HOME/matey",
+		ArgType::String,
+	)
+}
+
+fn open_cacher(cache_dir: Option<String>) -> Fallible<Cacher> {
+	Ok(match cache_dir {
+		Some(dir) => Cacher::with_folder(dir),
+		None => Cacher::new("matey")?,
+	})
+}
+
+fn build_candidates_parser(args: impl IntoIterator<Item = String>) -> ArgParser {
+	ArgParserBuilder::new(
+		args,
+		Arg::new(
+			"image",
+			Some("-i"),
+			Some("--image"),
+			"the image to extract colors from",
+			ArgType::String,
+		),
+	)
+	.usage(
+		"Usage: matey candidates [-i] FILE
+List the colors of FILE that can be used as a source, to be picked with --source-index",
+	)
+	.add_priority_opt(help_opt())
 	.build()
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
+fn list_candidates(args: impl IntoIterator<Item = String>) -> Fallible<()> {
+	let mut image_path: Option<String> = None;
+
+	let mut parser = build_candidates_parser(args);
+	while let Some(arg) = parser.next() {
+		let (name, value) = arg?;
+		match name {
+			"image" => {
+				image_path = value;
+			}
+			"help" => {
+				parser.emit_help();
+				return Ok(());
+			}
+			_ => unreachable!(),
+		}
+	}
+
+	let image_path = match image_path {
+		Some(path) => path,
+		None => return Err(Error::Usage("Please provide an image with -i".to_string())),
+	};
+
+	let buffer = fs::read(&image_path).map_err(|source| Error::Image {
+		path: Some(image_path.clone().into()),
+		source: Box::new(source),
+	})?;
+	let candidates = extract_candidates(&buffer).map_err(|e| e.with_path(&image_path))?;
+	for (i, color) in candidates.iter().enumerate() {
+		print_swatch(color);
+		println!(" {:>2}  #{}", i, color.to_hex());
+	}
+
+	Ok(())
+}
+
+const CACHE_USAGE: &str = "Usage: matey cache list
+  or:  matey cache show HASH
+  or:  matey cache prune [--older-than AGE] [--max-size SIZE]
+  or:  matey cache clear
+Manage the themes cached with --use-cache";
+
+fn build_cache_parser(args: impl IntoIterator<Item = String>, action: &str) -> ArgParser {
+	match action {
+		"show" => ArgParserBuilder::new(
+			args,
+			Arg::new(
+				"hash",
+				None,
+				Some("--hash"),
+				"the entry to show, or the start of its name",
+				ArgType::String,
+			),
+		),
+		"prune" => ArgParserBuilder::without_last_opt(args)
+			.add_opt(Arg::new(
+				"older-than",
+				None,
+				Some("--older-than"),
+				"remove entries older than AGE (e.g. 12h, 30d, 2w)",
+				ArgType::String,
+			))
+			.add_opt(Arg::new(
+				"max-size",
+				None,
+				Some("--max-size"),
+				"remove the oldest entries until the cache fits in SIZE (e.g. 500K, 10M)",
+				ArgType::String,
+			)),
+		_ => ArgParserBuilder::without_last_opt(args),
+	}
+	.usage(CACHE_USAGE)
+	.add_opt(cache_dir_opt())
+	.add_priority_opt(help_opt())
+	.build()
+}
+
+fn list_cache(cacher: &Cacher) -> Fallible<()> {
+	for entry in cacher.entries()? {
+		match cacher.load(&entry) {
+			Ok(cached) => {
+				print_swatch(&cached.theme.source);
+				println!(
+					" {:<17}  {}  {:<11} {:+.1}  {}",
+					entry.short_name(),
+					format_time(entry.modified),
+					cached.theme.variant.name(),
+					cached.theme.contrast_level,
+					cached.image_path.display()
+				);
+			}
+			Err(e) => {
+				println!(
+					"       {:<17}  {}  unreadable: {}",
+					entry.short_name(),
+					format_time(entry.modified),
+					e
+				);
+			}
+		}
+	}
+
+	Ok(())
+}
+
+fn show_cache(cacher: &Cacher, entry: &CacheEntry) -> Fallible<()> {
+	let cached = cacher.load(entry)?;
+	let theme = &cached.theme;
+
+	println!("entry:    {}", entry.name);
+	println!("written:  {} UTC", format_time(entry.modified));
+	println!("image:    {}", cached.image_path.display());
+	println!("source:   #{}", theme.source.to_hex());
+	println!("variant:  {}", theme.variant.name());
+	println!("contrast: {:+.1}", theme.contrast_level);
+	println!("index:    {}", cached.source_index);
+
+	for (mode, scheme) in [("light", &theme.light), ("dark", &theme.dark)] {
+		println!("\n{}:", mode);
+		for (name, color) in scheme {
+			print!("  ");
+			print_swatch(color);
+			println!(" #{}  {}", color.to_hex(), name);
+		}
+	}
+
+	Ok(())
+}
+
+fn manage_cache(args: impl Iterator<Item = String>) -> Fallible<()> {
+	// the parser skips the first argument, which is then the action instead
+	// of the subcommand
+	let mut args = args.skip(1).peekable();
+	let action = match args.peek() {
+		Some(action) => action.clone(),
+		None => {
+			eprintln!("{}\n", CACHE_USAGE);
+			return Err(Error::Usage("Please provide a cache action".to_string()));
+		}
+	};
+	if matches!(action.as_str(), "-h" | "--help") {
+		println!("{}", CACHE_USAGE);
+		return Ok(());
+	}
+	if !matches!(action.as_str(), "list" | "show" | "prune" | "clear") {
+		eprintln!("{}\n", CACHE_USAGE);
+		return Err(Error::Usage(format!("unknown cache action {:?}", action)));
+	}
+
+	let mut hash: Option<String> = None;
+	let mut older_than: Option<Duration> = None;
+	let mut max_size: Option<u64> = None;
+	let mut cache_dir: Option<String> = None;
+
+	let mut parser = build_cache_parser(args, &action);
+	while let Some(arg) = parser.next() {
+		let (name, value) = arg?;
+		match name {
+			"hash" => {
+				hash = value;
+			}
+			"cache-dir" => {
+				cache_dir = value;
+			}
+			"older-than" => {
+				let value = value.unwrap();
+				older_than = match parse_age(&value) {
+					Some(age) => Some(age),
+					None => {
+						return Err(Error::Usage(format!(
+							"invalid age {}, expected e.g. 30d",
+							value
+						)));
+					}
+				};
+			}
+			"max-size" => {
+				let value = value.unwrap();
+				max_size = match parse_size(&value) {
+					Some(size) => Some(size),
+					None => {
+						return Err(Error::Usage(format!(
+							"invalid size {}, expected e.g. 10M",
+							value
+						)));
+					}
+				};
+			}
+			"help" => {
+				parser.emit_help();
+				return Ok(());
+			}
+			_ => unreachable!(),
+		}
+	}
+
+	let cacher = open_cacher(cache_dir)?;
+	match action.as_str() {
+		"list" => list_cache(&cacher)?,
+		"show" => {
+			let Some(hash) = hash else {
+				return Err(Error::Usage("Please provide the entry to show".to_string()));
+			};
+			let entry = match cacher.find(&hash)?.as_slice() {
+				[entry] => entry.clone(),
+				[] => {
+					return Err(Error::Usage(format!("no cache entry starts with {}", hash)));
+				}
+				_ => {
+					return Err(Error::Usage(format!(
+						"{} matches several cache entries",
+						hash
+					)));
+				}
+			};
+			show_cache(&cacher, &entry)?;
+		}
+		"prune" => {
+			if older_than.is_none() && max_size.is_none() {
+				return Err(Error::Usage(
+					"Please provide --older-than or --max-size".to_string(),
+				));
+			}
+			let removed = cacher.prune(older_than, max_size)?;
+			let freed: u64 = removed.iter().map(|entry| entry.size).sum();
+			println!("removed {} entries, {} bytes", removed.len(), freed);
+		}
+		"clear" => {
+			let removed = cacher.clear()?;
+			println!(
+				"removed {} entries from {}",
+				removed,
+				cacher.folder().display()
+			);
+		}
+		_ => unreachable!(),
+	}
+
+	Ok(())
+}
+
+/// What the command line asks for, shared by a normal run and `matey watch`.
+struct Options {
+	template_files: Vec<PathBuf>,
+	image_path: Option<String>,
+	seed_color: Option<Argb>,
+	variant: MateyVariant,
+	contrast_level: f64,
+	source_index: usize,
+	cache_dir: Option<String>,
+	use_cache: bool,
+	is_dark: bool,
+	dry_run: bool,
+	no_configs: bool,
+}
+
+/// Also sets up the logger. Returns `None` if only the help or the version was
+/// asked for.
+fn parse_options(mut parser: ArgParser) -> Fallible<Option<Options>> {
 	let mut template_files: Vec<PathBuf> = Vec::new();
 
 	let mut image_path: Option<String> = None;
+	let mut seed_color: Option<String> = None;
+	let mut variant_name: Option<String> = None;
+	let mut contrast: Option<String> = None;
+	let mut source_index: Option<String> = None;
+	let mut cache_dir: Option<String> = None;
 	let mut use_cache = false;
 	let mut is_dark = true;
 	let mut dry_run = false;
 	let mut no_configs = false;
 	let mut log_level = LevelFilter::Warn;
 
-	let mut parser = build_arg_parser();
-	while let Some((name, value)) = parser.next() {
+	while let Some(arg) = parser.next() {
+		let (name, value) = arg?;
 		match name {
 			"template" => {
-				let path: PathBuf = value.unwrap().into();
-				template_files.push(absolute(path)?);
+				let path = value.unwrap();
+				template_files
+					.push(absolute(&path).map_err(|e| Error::io("could not resolve", &path, e))?);
 			}
 			"image" => {
 				image_path = Some(value.unwrap());
 			}
+			"color" => {
+				seed_color = value;
+			}
+			"variant" => {
+				variant_name = value;
+			}
+			"source-index" => {
+				source_index = value;
+			}
+			"contrast" => {
+				contrast = value;
+			}
 			"use-cache" => {
 				use_cache = true;
 			}
+			"cache-dir" => {
+				cache_dir = value;
+			}
 			"light" => {
 				is_dark = false;
 			}
@@ -160,72 +610,149 @@ fn main() -> Result<(), Box<dyn Error>> {
 			}
 			"help" => {
 				parser.emit_help();
-				return Ok(());
+				print_exit_codes();
+				return Ok(None);
 			}
 			"version" => {
 				println!("matey {}", env!("CARGO_PKG_VERSION"));
-				return Ok(());
-			}
-			other => {
-				panic!("Unknown option {}", other);
+				return Ok(None);
 			}
+			_ => unreachable!(),
 		}
 	}
 
-	SimpleLogger::new().with_level(log_level).init().unwrap();
+	SimpleLogger::new()
+		.with_level(log_level)
+		.init()
+		.map_err(|e| Error::Other(format!("could not start logging: {}", e)))?;
 
-	let config_path = if !no_configs {
-		match try_load_from_config(&mut template_files) {
-			Err(e) => {
-				error!("could not load templates form config: {}", e);
-				None
-			}
-			Ok(mut path) => {
-				path.pop();
-				Some(path)
-			}
-		}
-	} else {
-		None
+	let variant = match variant_name {
+		Some(name) => match MateyVariant::from_name(&name) {
+			Some(variant) => variant,
+			None => return Err(Error::Usage(format!("unknown variant {}", name))),
+		},
+		None => MateyVariant::default(),
 	};
 
-	let image_path = if let Some(file) = image_path {
-		file
-	} else {
-		panic!("Please provide an image with -f")
+	let contrast_level = match contrast {
+		Some(contrast) => match contrast.parse::<f64>() {
+			Ok(level) if (-1.0..=1.0).contains(&level) => level,
+			_ => {
+				return Err(Error::Usage(format!(
+					"contrast level must be a number between -1.0 and 1.0, got {}",
+					contrast
+				)));
+			}
+		},
+		None => 0.0,
 	};
 
-	let buffer = fs::read(&image_path).expect("Could not read image");
+	let source_index = match source_index {
+		Some(index) => match index.parse::<usize>() {
+			Ok(index) => index,
+			Err(_) => return Err(Error::Usage(format!("invalid source index {}", index))),
+		},
+		None => 0,
+	};
 
-	let scheme = if use_cache {
-		let cacher = Cacher::new("matey")?;
-		let handle = cacher.get(&buffer);
-		match cacher.get_cache(&handle) {
-			Some(Ok(theme)) => theme,
-			Some(Err(e)) => {
-				error!("error loading cache: {}", e);
-				compute_theme(&buffer)
+	let seed_color = match seed_color {
+		Some(color) => match color.parse::<Argb>() {
+			Ok(color) => Some(color),
+			Err(_) => {
+				return Err(Error::Usage(format!(
+					"invalid color {}, expected #RRGGBB",
+					color
+				)));
 			}
-			None => {
-				let theme = compute_theme(&buffer);
+		},
+		None => None,
+	};
 
-				if cacher.save_cache(&handle, &theme).is_err() {
-					error!("could not save theme to cache");
-				}
+	if cache_dir.is_some() && !use_cache {
+		return Err(Error::Usage("--cache-dir is only used with -u".to_string()));
+	}
 
-				theme
+	if image_path.is_some() && seed_color.is_some() {
+		warn!("both an image and a color were given, the image will be ignored");
+	} else if image_path.is_none() && seed_color.is_none() {
+		return Err(Error::Usage(
+			"Please provide an image with -i or a color with --color".to_string(),
+		));
+	}
+
+	Ok(Some(Options {
+		template_files,
+		image_path,
+		seed_color,
+		variant,
+		contrast_level,
+		source_index,
+		cache_dir,
+		use_cache,
+		is_dark,
+		dry_run,
+		no_configs,
+	}))
+}
+
+/// Returns the templates to render and matey's config folder, if it is used.
+fn find_templates(options: &Options) -> (Vec<PathBuf>, Option<PathBuf>) {
+	let mut template_files = options.template_files.clone();
+
+	let config_folder = if !options.no_configs {
+		match try_load_from_config(&mut template_files) {
+			Err(e) => {
+				error!("could not load templates form config: {}", e);
+				None
 			}
+			Ok(path) => Some(path),
 		}
 	} else {
-		compute_theme(&buffer)
+		None
 	};
 
-	let theme = if is_dark { &scheme.dark } else { &scheme.light };
+	(template_files, config_folder)
+}
 
-	let additional = [
+fn load_themes(options: &Options) -> Fallible<ThemeCache> {
+	Ok(match (&options.image_path, options.seed_color) {
+		(_, Some(color)) => ThemeCache::from_color(color),
+		(Some(image_path), None) => {
+			let buffer = fs::read(image_path).map_err(|source| Error::Image {
+				path: Some(image_path.into()),
+				source: Box::new(source),
+			})?;
+
+			let cacher = if options.use_cache {
+				let cacher = open_cacher(options.cache_dir.clone())?;
+				cacher.check_writable()?;
+				Some(cacher)
+			} else {
+				None
+			};
+			ThemeCache::from_image(
+				absolute(image_path).map_err(|e| Error::io("could not resolve", image_path, e))?,
+				buffer,
+				cacher,
+				options.source_index,
+			)
+		}
+		(None, None) => unreachable!(),
+	})
+}
+
+fn additional_variables(
+	options: &Options,
+	config_folder: Option<&Path>,
+) -> Vec<(String, IndexableVariable)> {
+	vec![
 		(
 			"image".to_string(),
-			IndexableVariable::plain(image_path.clone().into_bytes()),
+			IndexableVariable::plain(if options.seed_color.is_some() {
+				Vec::new()
+			} else {
+				options.image_path.clone().unwrap_or_default().into_bytes()
+			}),
 		),
 		(
 			"HOME".to_string(),
@@ -234,50 +761,300 @@ fn main() -> Result<(), Box<dyn Error>> {
 		(
 			"CONFIG".to_string(),
 			IndexableVariable::plain(
-				config_path
-					.unwrap_or_default()
-					.into_os_string()
-					.into_encoded_bytes(),
+				config_folder
+					.and_then(Path::parent)
+					.unwrap_or(Path::new(""))
+					.as_os_str()
+					.as_encoded_bytes()
+					.to_vec(),
 			),
 		),
-	];
+	]
+}
 
-	let hashmap = (theme)
-		.into_iter()
-		.map::<(String, IndexableVariable), _>(|(key, color)| (key.to_string(), (*color).into()))
-		.chain(additional)
-		.collect();
+/// Parses the template at `path` and writes its output.
+fn render_template(
+	path: &Path,
+	themes: &mut ThemeCache,
+	options: &Options,
+	additional: &[(String, IndexableVariable)],
+) -> Fallible<()> {
+	info!("parsing {}", path.display());
+	let mut buf = String::new();
+	File::open(path)
+		.and_then(|mut file| file.read_to_string(&mut buf))
+		.map_err(|e| Error::io("could not read", path, e))?;
 
+	let config = parse_config(path, &buf)?;
+	if log_enabled!(Level::Warn) {
+		for warning in config.warnings() {
+			eprintln!("{}\n", warning);
+		}
+	}
+
+	if options.seed_color.is_some() && config.uses_key("image") {
+		warn!(
+			"{} uses {{image}}, which is empty when the theme comes from --color",
+			path.display()
+		);
+	}
+
+	let is_dark = options.is_dark;
+	let variant = config.variant().unwrap_or(options.variant);
+	let contrast_level = config.contrast_level().unwrap_or(options.contrast_level);
+	let theme = themes.get(variant, contrast_level)?;
+
+	// changed outputs are printed so reload scripts can pick what to reload
+	if options.dry_run {
+		let rendered = config.render(theme, is_dark, additional)?;
+		if rendered.is_up_to_date() {
+			info!("{} is up to date", rendered.path.display());
+		} else {
+			println!("{}", rendered.path.display());
+		}
+		return Ok(());
+	}
+
+	let output = config.write(theme, is_dark, additional)?;
+	if output.changed {
+		println!("{}", output.path.display());
+	} else {
+		info!("{} is up to date", output.path.display());
+	}
+
+	Ok(())
+}
+
+/// Logs why a template couldn't be written.
+fn report_template_error(path: &Path, e: &Error) {
+	match e {
+		// diagnostics span several lines, they don't fit in a log line
+		Error::Parse(_) => {
+			if log_enabled!(Level::Error) {
+				eprintln!("{}\n", e);
+			}
+		}
+		e => error!("while writing template {}: {}", path.display(), e),
+	}
+}
+
+/// Renders every template, returning the exit code of the last one that
+/// failed.
+fn render_templates(
+	template_files: &[PathBuf],
+	themes: &mut ThemeCache,
+	options: &Options,
+	additional: &[(String, IndexableVariable)],
+) -> ExitCode {
+	let mut code = ExitCode::Success;
 	for path in template_files {
-		info!("parsing {}", path.display());
-		let mut file = match File::open(&path) {
-			Ok(file) => file,
-			Err(e) => {
-				error!("while opening {}: {}", path.display(), e);
-				continue;
+		if let Err(e) = render_template(path, themes, options, additional) {
+			report_template_error(path, &e);
+			code = e.exit_code();
+		}
+	}
+
+	code
+}
+
+fn print_exit_codes() {
+	println!("\nExit status:");
+	for code in ExitCode::ALL {
+		println!("  {}  {}", code as u8, code.description());
+	}
+}
+
+const WATCH_USAGE: &str = "Usage: matey watch [OPTIONS]... [-i] FILE
+  or:  matey watch [OPTIONS]... --color COLOR
+Write configs like matey does, then write them again whenever FILE or the
+templates change. FILE can be a symlink to the current wallpaper";
+
+fn watch(args: impl IntoIterator<Item = String>) -> Fallible<()> {
+	let Some(options) = parse_options(build_arg_parser(args).usage(WATCH_USAGE).build())? else {
+		return Ok(());
+	};
+
+	let mut watcher = Watcher::new()?;
+	let (template_files, config_folder) = find_templates(&options);
+	let additional = additional_variables(&options, config_folder.as_deref());
+
+	if let Some(folder) = &config_folder {
+		watcher.watch_folder(folder)?;
+	}
+	for path in &options.template_files {
+		watcher.watch_file(path)?;
+	}
+	let image_path = match (&options.image_path, options.seed_color) {
+		(Some(path), None) => {
+			Some(absolute(path).map_err(|e| Error::io("could not resolve", path, e))?)
+		}
+		_ => None,
+	};
+	if let Some(path) = &image_path {
+		watcher.watch_file(path)?;
+	}
+
+	let mut themes = load_themes(&options)?;
+	render_templates(&template_files, &mut themes, &options, &additional);
+
+	loop {
+		let changed = watcher.wait()?;
+		let (template_files, _) = find_templates(&options);
+
+		// ln -sf removes the link before creating the new one, the templates
+		// that changed meanwhile are still written below
+		let changed_image = image_path
+			.as_ref()
+			.filter(|image| file_changed(image, &changed) && image.exists());
+
+		if let Some(image) = changed_image {
+			info!("{} changed, regenerating the theme", image.display());
+			// the link may point to another folder now, failing to watch it
+			// only means changes made there are missed, the link's own folder
+			// is still watched
+			if let Err(e) = watcher.watch_file(image) {
+				warn!("{}", e);
 			}
-		};
-		let mut buf = String::new();
+			match load_themes(&options) {
+				Ok(new_themes) => {
+					themes = new_themes;
+					render_templates(&template_files, &mut themes, &options, &additional);
+					continue;
+				}
+				// the templates that changed are written with the old theme
+				Err(e) => error!("{}", e),
+			}
+		}
+
+		let changed: Vec<_> = template_files
+			.into_iter()
+			.filter(|path| changed.contains(path) && path.is_file())
+			.collect();
+		render_templates(&changed, &mut themes, &options, &additional);
+	}
+}
+
+const CHECK_USAGE: &str = "Usage: matey check [FILE]...
+Report mistakes in templates without generating a theme, exiting with 4 if
+there are any. Without FILEs, the templates in the config folder are checked";
+
+fn build_check_parser(args: impl IntoIterator<Item = String>) -> ArgParser {
+	ArgParserBuilder::new(
+		args,
+		Arg::new(
+			"template",
+			Some("-t"),
+			Some("--template"),
+			"a template to check",
+			ArgType::String,
+		),
+	)
+	.usage(CHECK_USAGE)
+	.repeat_last_opt()
+	.add_priority_opt(help_opt())
+	.build()
+}
 
-		if let Err(e) = file.read_to_string(&mut buf) {
-			error!("while reading {}: {}", path.display(), e);
-			continue;
+fn check(args: impl IntoIterator<Item = String>) -> Fallible<ExitCode> {
+	let mut template_files: Vec<PathBuf> = Vec::new();
+
+	let mut parser = build_check_parser(args);
+	while let Some(arg) = parser.next() {
+		let (name, value) = arg?;
+		match name {
+			"template" => {
+				template_files.push(value.unwrap().into());
+			}
+			"help" => {
+				parser.emit_help();
+				print_exit_codes();
+				return Ok(ExitCode::Success);
+			}
+			_ => unreachable!(),
 		}
+	}
+
+	if template_files.is_empty() {
+		try_load_from_config(&mut template_files)?;
+	}
 
-		let config = match parse_config(&path, &buf) {
-			Ok(config) => config,
+	let mut errors = 0;
+	let mut warnings = 0;
+	for path in &template_files {
+		let source = match fs::read_to_string(path) {
+			Ok(source) => source,
 			Err(e) => {
-				error!("while parsing {}: {}", path.display(), e);
+				eprintln!("error: could not read {}: {}\n", path.display(), e);
+				errors += 1;
 				continue;
 			}
 		};
-		if !dry_run {
-			if let Err(e) = config.write(theme, &hashmap) {
-				error!("while writing template {}: {}", path.display(), e);
-				continue;
+
+		for diagnostic in check_config(path, &source) {
+			eprintln!("{}\n", diagnostic);
+			match diagnostic.severity {
+				Severity::Error => errors += 1,
+				Severity::Warning => warnings += 1,
 			}
 		}
 	}
 
-	Ok(())
+	if errors + warnings > 0 {
+		eprintln!(
+			"{} error(s) and {} warning(s) in {} template(s)",
+			errors,
+			warnings,
+			template_files.len()
+		);
+		return Ok(ExitCode::Template);
+	}
+
+	Ok(ExitCode::Success)
+}
+
+fn run() -> Fallible<ExitCode> {
+	// the parsers skip the first argument, which is then the subcommand
+	// instead of the program
+	let mut args = env::args().peekable();
+	let program = args.next().unwrap_or_default();
+	match args.peek().map(String::as_str) {
+		Some("candidates") => return list_candidates(args).map(|_| ExitCode::Success),
+		Some("cache") => return manage_cache(args).map(|_| ExitCode::Success),
+		Some("watch") => return watch(args).map(|_| ExitCode::Success),
+		Some("check") => return check(args),
+		_ => {}
+	}
+
+	let Some(options) = parse_options(build_arg_parser(iter::once(program).chain(args)).build())?
+	else {
+		return Ok(ExitCode::Success);
+	};
+
+	let (template_files, config_folder) = find_templates(&options);
+	let additional = additional_variables(&options, config_folder.as_deref());
+
+	let mut themes = load_themes(&options)?;
+	themes.get(options.variant, options.contrast_level)?;
+
+	Ok(render_templates(
+		&template_files,
+		&mut themes,
+		&options,
+		&additional,
+	))
+}
+
+fn main() -> process::ExitCode {
+	let code = match run() {
+		Ok(code) => code,
+		Err(e) => {
+			eprintln!("error: {}", e);
+			if let Error::Usage(_) = e {
+				eprintln!("see --help for the options");
+			}
+			e.exit_code()
+		}
+	};
+
+	code.into()
 }
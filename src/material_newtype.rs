@@ -1,5 +1,14 @@
 use material_colors::color::Argb;
+use std::array;
+
+use material_colors::dynamic_color::{DynamicScheme, Variant};
+use material_colors::hct::Hct;
+use material_colors::palette::{Palette, TonalPalette};
 use material_colors::scheme::Scheme;
+use material_colors::scheme::variant::{
+	SchemeContent, SchemeExpressive, SchemeFidelity, SchemeFruitSalad, SchemeMonochrome,
+	SchemeNeutral, SchemeRainbow, SchemeTonalSpot, SchemeVibrant,
+};
 
 macro_rules! count {
     () => (0usize);
@@ -80,6 +89,21 @@ macro_rules! sametype {
                 ].into_iter()
             }
         }
+
+        impl $sname {
+            pub const FIELD_COUNT: usize = count!($($fname)+);
+
+            pub const FIELD_NAMES: [&'static str; count!($($fname)+)] = [
+                $(stringify!($fname)),+
+            ];
+
+            /// Builds the struct from its fields, in the order they are iterated.
+            pub fn from_fields(mut fields: impl Iterator<Item = $tname>) -> Option<Self> {
+                Some($sname {
+                    $($fname: fields.next()?,)+
+                })
+            }
+        }
     };
 }
 
@@ -161,15 +185,301 @@ sametype!(
 	}
 );
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MateyPalette {
+	tones: [MateyArgb; MateyPalette::TONE_COUNT],
+}
+
+impl MateyPalette {
+	pub const TONE_COUNT: usize = 101;
+
+	/// The tones `foreach` goes through, the full range can still be
+	/// addressed with [`MateyPalette::tone`].
+	pub const COMMON_TONES: [u8; 13] = [0, 10, 20, 30, 40, 50, 60, 70, 80, 90, 95, 99, 100];
+
+	pub fn from_tones(tones: [MateyArgb; MateyPalette::TONE_COUNT]) -> Self {
+		MateyPalette { tones }
+	}
+
+	pub fn tone(&self, tone: u8) -> Option<MateyArgb> {
+		self.tones.get(tone as usize).copied()
+	}
+
+	/// Reads a tone written the way `foreach palette` names them, so without
+	/// `+5` or `05`. `None` if it is out of range.
+	pub fn parse_tone(tone: &str) -> Option<u8> {
+		tone.parse::<u8>().ok().filter(|parsed| {
+			(*parsed as usize) < MateyPalette::TONE_COUNT && parsed.to_string() == tone
+		})
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = (u8, MateyArgb)> + '_ {
+		self.tones
+			.iter()
+			.enumerate()
+			.map(|(tone, color)| (tone as u8, *color))
+	}
+}
+
+impl From<&TonalPalette> for MateyPalette {
+	fn from(palette: &TonalPalette) -> Self {
+		MateyPalette {
+			tones: array::from_fn(|tone| palette.tone(tone as i32).into()),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MateyPalettes {
+	pub primary: MateyPalette,
+	pub secondary: MateyPalette,
+	pub tertiary: MateyPalette,
+	pub neutral: MateyPalette,
+	pub neutral_variant: MateyPalette,
+	pub error: MateyPalette,
+}
+
+impl MateyPalettes {
+	pub const NAMES: [&'static str; 6] = [
+		"primary",
+		"secondary",
+		"tertiary",
+		"neutral",
+		"neutral_variant",
+		"error",
+	];
+
+	/// The palettes a variant derives from the source color. They don't
+	/// depend on the mode or the contrast level, only the schemes do.
+	pub fn from_source(source: Argb, variant: MateyVariant) -> Self {
+		let source = Hct::new(source);
+		let palette: fn(&Hct, &Palette) -> TonalPalette = match variant {
+			MateyVariant::Monochrome => SchemeMonochrome::palette,
+			MateyVariant::Neutral => SchemeNeutral::palette,
+			MateyVariant::TonalSpot => SchemeTonalSpot::palette,
+			MateyVariant::Vibrant => SchemeVibrant::palette,
+			MateyVariant::Expressive => SchemeExpressive::palette,
+			MateyVariant::Fidelity => SchemeFidelity::palette,
+			MateyVariant::Content => SchemeContent::palette,
+			MateyVariant::Rainbow => SchemeRainbow::palette,
+			MateyVariant::FruitSalad => SchemeFruitSalad::palette,
+		};
+
+		MateyPalettes {
+			primary: (&palette(&source, &Palette::Primary)).into(),
+			secondary: (&palette(&source, &Palette::Secondary)).into(),
+			tertiary: (&palette(&source, &Palette::Tertiary)).into(),
+			neutral: (&palette(&source, &Palette::Neutral)).into(),
+			neutral_variant: (&palette(&source, &Palette::NeutralVariant)).into(),
+			error: (&palette(&source, &Palette::Error)).into(),
+		}
+	}
+
+	pub fn get(&self, name: &str) -> Option<&MateyPalette> {
+		self.into_iter()
+			.find_map(|(palette, value)| (palette == name).then_some(value))
+	}
+}
+
+impl From<&DynamicScheme> for MateyPalettes {
+	fn from(scheme: &DynamicScheme) -> Self {
+		MateyPalettes {
+			primary: (&scheme.primary_palette).into(),
+			secondary: (&scheme.secondary_palette).into(),
+			tertiary: (&scheme.tertiary_palette).into(),
+			neutral: (&scheme.neutral_palette).into(),
+			neutral_variant: (&scheme.neutral_variant_palette).into(),
+			error: (&scheme.error_palette).into(),
+		}
+	}
+}
+
+impl<'a> IntoIterator for &'a MateyPalettes {
+	type Item = (&'static str, &'a MateyPalette);
+	type IntoIter = std::array::IntoIter<Self::Item, 6>;
+
+	fn into_iter(self) -> Self::IntoIter {
+		[
+			("primary", &self.primary),
+			("secondary", &self.secondary),
+			("tertiary", &self.tertiary),
+			("neutral", &self.neutral),
+			("neutral_variant", &self.neutral_variant),
+			("error", &self.error),
+		]
+		.into_iter()
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum MateyVariant {
+	Monochrome,
+	Neutral,
+	#[default]
+	TonalSpot,
+	Vibrant,
+	Expressive,
+	Fidelity,
+	Content,
+	Rainbow,
+	FruitSalad,
+}
+
+impl MateyVariant {
+	pub const ALL: [MateyVariant; 9] = [
+		MateyVariant::Monochrome,
+		MateyVariant::Neutral,
+		MateyVariant::TonalSpot,
+		MateyVariant::Vibrant,
+		MateyVariant::Expressive,
+		MateyVariant::Fidelity,
+		MateyVariant::Content,
+		MateyVariant::Rainbow,
+		MateyVariant::FruitSalad,
+	];
+
+	/// Accepts the variant name in snake_case, dash-case or flatcase.
+	pub fn from_name(name: &str) -> Option<Self> {
+		let name = name.to_ascii_lowercase().replace(['-', '_'], "");
+		MateyVariant::ALL
+			.into_iter()
+			.find(|variant| variant.name().replace('_', "") == name)
+	}
+
+	pub const fn name(&self) -> &'static str {
+		match self {
+			MateyVariant::Monochrome => "monochrome",
+			MateyVariant::Neutral => "neutral",
+			MateyVariant::TonalSpot => "tonal_spot",
+			MateyVariant::Vibrant => "vibrant",
+			MateyVariant::Expressive => "expressive",
+			MateyVariant::Fidelity => "fidelity",
+			MateyVariant::Content => "content",
+			MateyVariant::Rainbow => "rainbow",
+			MateyVariant::FruitSalad => "fruit_salad",
+		}
+	}
+}
+
+impl From<MateyVariant> for Variant {
+	fn from(value: MateyVariant) -> Self {
+		match value {
+			MateyVariant::Monochrome => Variant::Monochrome,
+			MateyVariant::Neutral => Variant::Neutral,
+			MateyVariant::TonalSpot => Variant::TonalSpot,
+			MateyVariant::Vibrant => Variant::Vibrant,
+			MateyVariant::Expressive => Variant::Expressive,
+			MateyVariant::Fidelity => Variant::Fidelity,
+			MateyVariant::Content => Variant::Content,
+			MateyVariant::Rainbow => Variant::Rainbow,
+			MateyVariant::FruitSalad => Variant::FruitSalad,
+		}
+	}
+}
+
 #[derive(Debug, Clone)]
-#[repr(C)]
 pub struct MateyTheme {
+	pub source: MateyArgb,
 	pub light: MateyScheme,
 	pub dark: MateyScheme,
+	pub palettes: MateyPalettes,
+	pub variant: MateyVariant,
+	pub contrast_level: f64,
 }
 
 impl MateyTheme {
-	pub fn new(light: MateyScheme, dark: MateyScheme) -> Self {
-		MateyTheme { light, dark }
+	pub fn new(
+		source: MateyArgb,
+		light: MateyScheme,
+		dark: MateyScheme,
+		palettes: MateyPalettes,
+		variant: MateyVariant,
+		contrast_level: f64,
+	) -> Self {
+		MateyTheme {
+			source,
+			light,
+			dark,
+			palettes,
+			variant,
+			contrast_level,
+		}
+	}
+
+	/// `contrast_level` goes from -1.0 (reduced) to 1.0 (high), 0.0 being the
+	/// standard contrast. Values outside that range are clamped.
+	pub fn from_source(source: Argb, variant: MateyVariant, contrast_level: f64) -> Self {
+		let contrast_level = contrast_level.clamp(-1.0, 1.0);
+		let scheme = |is_dark| {
+			DynamicScheme::by_variant(source, &variant.into(), is_dark, Some(contrast_level))
+		};
+		let (light, dark) = (scheme(false), scheme(true));
+
+		MateyTheme::new(
+			source.into(),
+			Scheme::from(light).into(),
+			Scheme::from(dark).into(),
+			MateyPalettes::from_source(source, variant),
+			variant,
+			contrast_level,
+		)
+	}
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use crate::{
+		material_newtype::{MateyPalette, MateyPalettes, MateyTheme, MateyVariant},
+		test_util::test_source,
+	};
+	#[allow(unused_imports)]
+	use material_colors::dynamic_color::DynamicScheme;
+
+	#[test]
+	fn test_variants() {
+		let source = test_source();
+		let roles = |variant| {
+			let dark = MateyTheme::from_source(source, variant, 0.0).dark;
+			(dark.primary, dark.secondary, dark.tertiary, dark.surface)
+		};
+
+		assert_eq!(
+			MateyVariant::from_name("Fruit-Salad"),
+			Some(MateyVariant::FruitSalad)
+		);
+		assert_eq!(
+			MateyVariant::from_name("tonalspot"),
+			Some(MateyVariant::TonalSpot)
+		);
+		assert_eq!(MateyVariant::from_name("pastel"), None);
+
+		let tonal_spot = roles(MateyVariant::TonalSpot);
+		assert_eq!(roles(MateyVariant::default()), tonal_spot);
+		for variant in MateyVariant::ALL {
+			if variant != MateyVariant::TonalSpot {
+				assert_ne!(roles(variant), tonal_spot, "{}", variant.name());
+			}
+		}
+
+		let (monochrome, ..) = roles(MateyVariant::Monochrome);
+		assert!(monochrome.red == monochrome.green && monochrome.green == monochrome.blue);
+	}
+
+	#[test]
+	fn test_palettes() {
+		let source = test_source();
+		for variant in MateyVariant::ALL {
+			let palettes = MateyPalettes::from_source(source, variant);
+			for is_dark in [false, true] {
+				let scheme = DynamicScheme::by_variant(source, &variant.into(), is_dark, Some(0.5));
+				assert_eq!(MateyPalettes::from(&scheme), palettes, "{}", variant.name());
+			}
+		}
+
+		assert_eq!(MateyPalette::parse_tone("0"), Some(0));
+		assert_eq!(MateyPalette::parse_tone("100"), Some(100));
+		for tone in ["101", "05", "+5", "-1", "", "4O"] {
+			assert_eq!(MateyPalette::parse_tone(tone), None, "{}", tone);
+		}
 	}
 }
@@ -0,0 +1,300 @@
+use std::{
+	ffi::OsString,
+	fs::{self, File, Permissions},
+	io::{ErrorKind, Write},
+	path::{Path, PathBuf},
+	process,
+	time::SystemTime,
+};
+
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+
+use log::info;
+
+use crate::error::{Error, Fallible};
+
+/// How the output of a template gets written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct WriteOptions {
+	pub backup: Backup,
+	/// Set with `#permissions "0600"`, otherwise new files get the default
+	/// permissions and existing ones keep theirs. Only used on Unix.
+	pub permissions: Option<u32>,
+}
+
+/// Parses permissions written in octal, such as `0600` or `644`.
+pub fn parse_permissions(permissions: &str) -> Option<u32> {
+	u32::from_str_radix(permissions, 8)
+		.ok()
+		.filter(|&mode| mode <= 0o7777)
+}
+
+/// What to keep of a file before it gets overwritten, set with `#backup`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backup {
+	#[default]
+	None,
+	/// `file.bak`, replaced every time
+	Bak,
+	/// `file.{unix time}.bak`, one per write
+	Timestamped,
+}
+
+impl Backup {
+	pub fn from_name(name: &str) -> Option<Self> {
+		match name {
+			"none" => Some(Backup::None),
+			"bak" => Some(Backup::Bak),
+			"timestamp" | "timestamped" => Some(Backup::Timestamped),
+			_ => None,
+		}
+	}
+
+	fn path(&self, path: &Path) -> Option<PathBuf> {
+		let suffix = match self {
+			Backup::None => return None,
+			Backup::Bak => ".bak".to_string(),
+			Backup::Timestamped => {
+				let secs = SystemTime::now()
+					.duration_since(SystemTime::UNIX_EPOCH)
+					.map_or(0, |d| d.as_secs());
+				format!(".{}.bak", secs)
+			}
+		};
+
+		Some(with_suffix(path, &suffix))
+	}
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+	let mut name = OsString::from(path.file_name().unwrap_or_default());
+	name.push(suffix);
+	path.with_file_name(name)
+}
+
+/// Writes `contents` to a temporary file next to `path` and renames it over
+/// `path`, so the file is never left half written. If `path` is a symlink,
+/// the file it points to is replaced and the link is kept. Missing parent
+/// folders are created.
+pub fn write_atomically(path: &Path, contents: &[u8], options: &WriteOptions) -> Fallible<()> {
+	let path = match fs::canonicalize(path) {
+		Ok(target) => target,
+		Err(e) if e.kind() == ErrorKind::NotFound => path.to_path_buf(),
+		Err(e) => return Err(Error::io("could not resolve", path, e)),
+	};
+	let existing = fs::metadata(&path).ok();
+
+	if let Some(parent) = path.parent()
+		&& !parent.as_os_str().is_empty()
+		&& !parent.exists()
+	{
+		info!("creating {}", parent.display());
+		fs::create_dir_all(parent).map_err(|e| Error::io("could not create folder", parent, e))?;
+	}
+
+	let tmp_path = with_suffix(&path, &format!(".matey-{}.tmp", process::id()));
+	let write_tmp = || -> std::io::Result<()> {
+		let mut tmp = File::create(&tmp_path)?;
+		// before writing, so nothing is readable by who shouldn't
+		if let Some(mode) = options.permissions {
+			tmp.set_permissions(with_mode(tmp.metadata()?.permissions(), mode))?;
+		} else if let Some(existing) = &existing {
+			tmp.set_permissions(existing.permissions())?;
+		}
+		tmp.write_all(contents)?;
+		tmp.sync_all()
+	};
+
+	if let Err(e) = write_tmp() {
+		let _ = fs::remove_file(&tmp_path);
+		return Err(Error::io("could not write", &path, e));
+	}
+
+	if existing.is_some()
+		&& let Some(backup_path) = options.backup.path(&path)
+		&& let Err(e) = fs::copy(&path, &backup_path)
+	{
+		let _ = fs::remove_file(&tmp_path);
+		return Err(Error::io(
+			format!("could not back up {} to", path.display()),
+			&backup_path,
+			e,
+		));
+	}
+
+	fs::rename(&tmp_path, &path).map_err(|e| {
+		let _ = fs::remove_file(&tmp_path);
+		Error::io("could not replace", &path, e)
+	})
+}
+
+/// Gives `path` the permissions asked for with `#permissions`, for outputs
+/// that didn't need to be written again.
+pub fn apply_permissions(path: &Path, options: &WriteOptions) -> Fallible<()> {
+	let Some(mode) = options.permissions else {
+		return Ok(());
+	};
+
+	let metadata = fs::metadata(path).map_err(|e| Error::io("could not read", path, e))?;
+	if mode_of(&metadata.permissions()).is_some_and(|current| current != mode) {
+		fs::set_permissions(path, with_mode(metadata.permissions(), mode))
+			.map_err(|e| Error::io("could not set permissions of", path, e))?;
+	}
+
+	Ok(())
+}
+
+/// `permissions` with the mode bits set to `mode`. Modes only exist on Unix,
+/// elsewhere `#permissions` is ignored.
+#[cfg(unix)]
+fn with_mode(mut permissions: Permissions, mode: u32) -> Permissions {
+	permissions.set_mode(mode);
+	permissions
+}
+
+#[cfg(not(unix))]
+fn with_mode(permissions: Permissions, _mode: u32) -> Permissions {
+	permissions
+}
+
+#[cfg(unix)]
+fn mode_of(permissions: &Permissions) -> Option<u32> {
+	Some(permissions.mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn mode_of(_permissions: &Permissions) -> Option<u32> {
+	None
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use crate::{
+		output::{Backup, WriteOptions, apply_permissions, parse_permissions, write_atomically},
+		test_util::TempFolder,
+	};
+	#[allow(unused_imports)]
+	#[cfg(unix)]
+	use std::os::unix::fs::{PermissionsExt, symlink};
+	#[allow(unused_imports)]
+	use std::{
+		fs::{self, File},
+		io::Read,
+	};
+
+	#[test]
+	fn test_write_atomically() {
+		let folder = TempFolder::new("write");
+		let path = folder.join("tool/colors/out.conf");
+		let options = WriteOptions::default();
+
+		// missing folders are created
+		write_atomically(&path, b"one", &options).unwrap();
+		assert_eq!(fs::read(&path).unwrap(), b"one");
+
+		// the file is replaced rather than written in place, so whoever has
+		// the old one open still reads all of it
+		let mut old = File::open(&path).unwrap();
+		write_atomically(&path, b"two", &options).unwrap();
+		let mut contents = Vec::new();
+		old.read_to_end(&mut contents).unwrap();
+		assert_eq!(contents, b"one");
+		assert_eq!(fs::read(&path).unwrap(), b"two");
+
+		// and no temporary file is left behind
+		let names: Vec<_> = fs::read_dir(path.parent().unwrap())
+			.unwrap()
+			.map(|entry| entry.unwrap().file_name())
+			.collect();
+		assert_eq!(names, ["out.conf"]);
+
+		// symlinks are followed, the link stays a link
+		#[cfg(unix)]
+		{
+			let link = folder.join("link.conf");
+			symlink(&path, &link).unwrap();
+			write_atomically(&link, b"three", &options).unwrap();
+			assert!(link.is_symlink());
+			assert_eq!(fs::read(&path).unwrap(), b"three");
+		}
+	}
+
+	#[test]
+	fn test_backups() {
+		let folder = TempFolder::new("backup");
+		let path = folder.join("out.conf");
+		let write = |contents: &[u8], backup| {
+			let options = WriteOptions {
+				backup,
+				permissions: None,
+			};
+			write_atomically(&path, contents, &options).unwrap();
+		};
+		let backups = || {
+			let mut names: Vec<_> = fs::read_dir(&folder)
+				.unwrap()
+				.map(|entry| entry.unwrap().file_name().into_string().unwrap())
+				.filter(|name| name != "out.conf")
+				.collect();
+			names.sort();
+			names
+		};
+
+		// nothing to back up the first time
+		write(b"one", Backup::Bak);
+		assert!(backups().is_empty());
+
+		write(b"two", Backup::Bak);
+		write(b"three", Backup::Bak);
+		assert_eq!(backups(), ["out.conf.bak"]);
+		assert_eq!(fs::read(folder.join("out.conf.bak")).unwrap(), b"two");
+
+		fs::remove_file(folder.join("out.conf.bak")).unwrap();
+		write(b"four", Backup::Timestamped);
+		let names = backups();
+		assert_eq!(names.len(), 1);
+		let secs = names[0]
+			.strip_prefix("out.conf.")
+			.and_then(|name| name.strip_suffix(".bak"))
+			.unwrap();
+		assert!(secs.parse::<u64>().is_ok());
+		assert_eq!(fs::read(folder.join(&names[0])).unwrap(), b"three");
+
+		write(b"five", Backup::None);
+		assert_eq!(backups(), names);
+	}
+
+	#[test]
+	fn test_permissions() {
+		assert_eq!(parse_permissions("0600"), Some(0o600));
+		assert_eq!(parse_permissions("644"), Some(0o644));
+		assert_eq!(parse_permissions("4755"), Some(0o4755));
+		for permissions in ["0999", "rw-r--r--", "17777", ""] {
+			assert_eq!(parse_permissions(permissions), None, "{}", permissions);
+		}
+
+		#[cfg(unix)]
+		{
+			let folder = TempFolder::new("mode");
+			let path = folder.join("secret.conf");
+			let mode = |path| fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+			let options = WriteOptions {
+				backup: Backup::None,
+				permissions: parse_permissions("0600"),
+			};
+
+			write_atomically(&path, b"one", &options).unwrap();
+			assert_eq!(mode(&path), 0o600);
+
+			// kept when the file is written again without #permissions
+			write_atomically(&path, b"two", &WriteOptions::default()).unwrap();
+			assert_eq!(mode(&path), 0o600);
+
+			// and set on outputs that didn't change
+			fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+			apply_permissions(&path, &options).unwrap();
+			assert_eq!(mode(&path), 0o600);
+		}
+	}
+}
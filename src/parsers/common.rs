@@ -1,29 +1,19 @@
-use std::fmt;
-
-#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
-pub struct FileLocation {
-	line: usize,
-	column: usize,
-}
-
-impl FileLocation {
-	pub fn new() -> Self {
-		FileLocation { line: 1, column: 1 }
-	}
-
-	pub fn step(&mut self) {
-		self.column += 1;
-	}
-
-	pub fn nl(&mut self) {
-		self.column = 1;
-		self.line += 1;
-	}
+/// Which scheme a template is rendered with, `Auto` follows the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SchemeMode {
+	Dark,
+	Light,
+	#[default]
+	Auto,
 }
 
-impl fmt::Display for FileLocation {
-	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-		write!(f, "line: {}, col: {}", self.line, self.column)
+impl SchemeMode {
+	pub fn is_dark(&self, default: bool) -> bool {
+		match self {
+			SchemeMode::Dark => true,
+			SchemeMode::Light => false,
+			SchemeMode::Auto => default,
+		}
 	}
 }
 
@@ -46,10 +36,8 @@ fn from_snake(s: &str, make_first_upper: bool) -> String {
 	let mut fin = String::with_capacity(s.len());
 
 	let mut chunks = s.split('_').filter(|s| !s.is_empty());
-	if !make_first_upper {
-		if let Some(s) = chunks.next() {
-			fin.push_str(s);
-		}
+	if !make_first_upper && let Some(s) = chunks.next() {
+		fin.push_str(s);
 	}
 
 	for chunk in chunks {
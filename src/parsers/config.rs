@@ -1,15 +1,20 @@
-use std::collections::HashMap;
-use std::fs::File;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
 
 use log::warn;
 
-use super::common::RenamingScheme;
+use super::common::{RenamingScheme, SchemeMode};
 
+use crate::diagnostic::Diagnostic;
 use crate::error::Error;
 use crate::error::Fallible;
-use crate::material_newtype::MateyScheme;
+use crate::hook::{HOOK_TIMEOUT, run_hook};
+use crate::material_newtype::{MateyArgb, MateyPalette, MateyPalettes, MateyTheme, MateyVariant};
+use crate::output::{Backup, WriteOptions, apply_permissions, parse_permissions, write_atomically};
 use crate::parsers::templates::IndexableVariable;
 use crate::parsers::templates::Template;
+use crate::parsers::templates::Variables;
 
 #[derive(Debug)]
 enum TemplatedString<'a> {
@@ -20,6 +25,12 @@ enum TemplatedString<'a> {
 pub struct ConfigBuilder<'a> {
 	outfile: Option<TemplatedString<'a>>,
 	naming: RenamingScheme,
+	variant: Option<MateyVariant>,
+	contrast_level: Option<f64>,
+	mode: SchemeMode,
+	hooks: Vec<TemplatedString<'a>>,
+	region: Option<Region>,
+	output: WriteOptions,
 	templates: Vec<ConfigTemplate<'a>>,
 }
 
@@ -28,12 +39,23 @@ impl<'a> ConfigBuilder<'a> {
 		ConfigBuilder {
 			outfile: None,
 			naming: RenamingScheme::Snake,
+			variant: None,
+			contrast_level: None,
+			mode: SchemeMode::Auto,
+			hooks: Vec::new(),
+			region: None,
+			output: WriteOptions::default(),
 			templates: Vec::new(),
 		}
 	}
 
-	pub fn add_foreach_template<'b: 'a>(&mut self, template: Template<'b>) {
-		self.templates.push(ConfigTemplate::Foreach(template));
+	pub fn add_foreach_template<'b: 'a>(
+		&mut self,
+		target: ForeachTarget<'b>,
+		template: Template<'b>,
+	) {
+		self.templates
+			.push(ConfigTemplate::Foreach(target, template));
 	}
 
 	pub fn add_norm_template<'b: 'a>(&mut self, template: Template<'b>) {
@@ -48,7 +70,17 @@ impl<'a> ConfigBuilder<'a> {
 		self.outfile = Some(TemplatedString::Yes(outfile));
 	}
 
-	pub fn set_naming<'b: 'a>(&mut self, naming: &'b str) {
+	pub fn add_hook<'b: 'a>(&mut self, command: &'b str) {
+		self.hooks.push(TemplatedString::No(command));
+	}
+
+	pub fn add_hook_template<'b: 'a>(&mut self, command: Template<'b>) {
+		self.hooks.push(TemplatedString::Yes(command));
+	}
+
+	/// The setters below fall back to the default when given an unknown
+	/// value, returning what was wrong with it.
+	pub fn set_naming<'b: 'a>(&mut self, naming: &'b str) -> Result<(), String> {
 		use RenamingScheme::*;
 		self.naming = match naming {
 			"snake_case" => Snake,
@@ -59,30 +91,119 @@ impl<'a> ConfigBuilder<'a> {
 			"CamelCase" | "UpperCamelCase" => UpperCamel,
 			"lowercase" | "flatcase" => Flat,
 			_ => {
-				warn!("unknown naming convention {naming}");
-				Snake
+				self.naming = Snake;
+				return Err(format!("unknown naming convention {naming}"));
 			}
 		};
+		Ok(())
+	}
+
+	pub fn set_variant<'b: 'a>(&mut self, variant: &'b str) -> Result<(), String> {
+		self.variant = MateyVariant::from_name(variant);
+		if self.variant.is_none() {
+			return Err(format!("unknown variant {variant}"));
+		}
+		Ok(())
+	}
+
+	pub fn set_contrast<'b: 'a>(&mut self, contrast: &'b str) -> Result<(), String> {
+		self.contrast_level = contrast
+			.parse::<f64>()
+			.ok()
+			.filter(|level| (-1.0..=1.0).contains(level));
+		if self.contrast_level.is_none() {
+			return Err(format!(
+				"contrast level must be a number between -1.0 and 1.0, got {contrast}"
+			));
+		}
+		Ok(())
+	}
+
+	pub fn set_mode<'b: 'a>(&mut self, mode: &'b str) -> Result<(), String> {
+		self.mode = match mode {
+			"dark" => SchemeMode::Dark,
+			"light" => SchemeMode::Light,
+			"auto" => SchemeMode::Auto,
+			_ => {
+				self.mode = SchemeMode::Auto;
+				return Err(format!("unknown mode {mode}"));
+			}
+		};
+		Ok(())
+	}
+
+	pub fn set_region<'b: 'a>(&mut self, marker: &'b str) {
+		self.region = Some(Region::new(marker));
+	}
+
+	pub fn set_backup<'b: 'a>(&mut self, backup: &'b str) -> Result<(), String> {
+		let parsed = Backup::from_name(backup);
+		self.output.backup = parsed.unwrap_or_default();
+		if parsed.is_none() {
+			return Err(format!("unknown backup kind {backup}"));
+		}
+		Ok(())
+	}
+
+	pub fn set_permissions<'b: 'a>(&mut self, permissions: &'b str) -> Result<(), String> {
+		self.output.permissions = parse_permissions(permissions);
+		if self.output.permissions.is_none() {
+			return Err(format!("invalid permissions {permissions}"));
+		}
+		Ok(())
 	}
 
 	pub fn build(self) -> Fallible<Config<'a>> {
 		let outfile = if let Some(outfile) = self.outfile {
 			outfile
 		} else {
-			return Err(Error::Config("No output file specified".to_string()));
+			return Err(Error::config("no output file specified"));
 		};
 
 		Ok(Config {
 			outfile,
 			rename: self.naming,
+			variant: self.variant,
+			contrast_level: self.contrast_level,
+			mode: self.mode,
+			hooks: self.hooks,
+			region: self.region,
+			output: self.output,
 			templates: self.templates,
+			warnings: Vec::new(),
 		})
 	}
 }
 
+/// What a `foreach` template goes through: the colors of the selected scheme,
+/// of a specific one (`foreach dark`), the tones of every palette
+/// (`foreach palette`) or the tones of a single one (`foreach palette.primary`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForeachTarget<'a> {
+	Scheme,
+	Dark,
+	Light,
+	Palettes,
+	Palette(&'a str),
+}
+
+impl<'a> ForeachTarget<'a> {
+	pub fn from_selector(selector: &'a str) -> Option<Self> {
+		match selector.split_once('.') {
+			None if selector == "dark" => Some(ForeachTarget::Dark),
+			None if selector == "light" => Some(ForeachTarget::Light),
+			None if selector == "palette" => Some(ForeachTarget::Palettes),
+			Some(("palette", name)) if MateyPalettes::NAMES.contains(&name) => {
+				Some(ForeachTarget::Palette(name))
+			}
+			_ => None,
+		}
+	}
+}
+
 #[derive(Debug)]
 enum ConfigTemplate<'a> {
-	Foreach(Template<'a>),
+	Foreach(ForeachTarget<'a>, Template<'a>),
 	Norm(Template<'a>),
 }
 
@@ -90,47 +211,404 @@ enum ConfigTemplate<'a> {
 pub struct Config<'a> {
 	outfile: TemplatedString<'a>,
 	rename: RenamingScheme,
+	variant: Option<MateyVariant>,
+	contrast_level: Option<f64>,
+	mode: SchemeMode,
+	hooks: Vec<TemplatedString<'a>>,
+	region: Option<Region>,
+	output: WriteOptions,
 	templates: Vec<ConfigTemplate<'a>>,
+	warnings: Vec<Diagnostic>,
 }
 
 impl<'a> Config<'a> {
+	/// What looked wrong in the file without stopping it from being used,
+	/// such as unknown keys.
+	pub fn warnings(&self) -> &[Diagnostic] {
+		&self.warnings
+	}
+
+	pub(super) fn set_warnings(&mut self, warnings: Vec<Diagnostic>) {
+		self.warnings = warnings;
+	}
+
+	/// The variant requested with `#variant`, if any.
+	pub fn variant(&self) -> Option<MateyVariant> {
+		self.variant
+	}
+
+	/// The contrast level requested with `#contrast`, if any. It is always
+	/// between -1.0 and 1.0.
+	pub fn contrast_level(&self) -> Option<f64> {
+		self.contrast_level
+	}
+
+	/// Whether the template wants the dark scheme, `default` being the mode
+	/// asked for on the command line.
+	pub fn is_dark(&self, default: bool) -> bool {
+		self.mode.is_dark(default)
+	}
+
+	/// Whether `key` is printed anywhere, in the output path, the hooks or
+	/// the templates.
+	pub fn uses_key(&self, key: &str) -> bool {
+		let templates = self
+			.templates
+			.iter()
+			.map(|template| match template {
+				ConfigTemplate::Foreach(_, template) | ConfigTemplate::Norm(template) => template,
+			})
+			.chain([&self.outfile].into_iter().chain(&self.hooks).filter_map(
+				|string| match string {
+					TemplatedString::Yes(template) => Some(template),
+					TemplatedString::No(_) => None,
+				},
+			));
+
+		templates
+			.flat_map(|template| template.keys())
+			.any(|found| found.key == key)
+	}
+
+	/// Renders the templates and writes them to the output file, unless it
+	/// already holds exactly that. The `#hook` commands are run once the file
+	/// has been written, a failing hook is only logged.
 	pub fn write(
 		&self,
-		scheme: &MateyScheme,
-		hashmap: &HashMap<String, IndexableVariable>,
-	) -> Fallible<()> {
-		let mut file = match &self.outfile {
+		theme: &MateyTheme,
+		is_dark: bool,
+		additional: &[(String, IndexableVariable)],
+	) -> Fallible<Output> {
+		let rendered = self.render(theme, is_dark, additional)?;
+		let changed = !rendered.is_up_to_date();
+
+		if changed {
+			write_atomically(&rendered.path, &rendered.contents, &self.output)?;
+			let variables = Variables::new(theme, self.is_dark(is_dark), additional);
+
+			for hook in &self.hooks {
+				let command = match hook {
+					TemplatedString::Yes(template) => {
+						let mut command = Vec::new();
+						template
+							.run_for_shell(&mut command, &variables)
+							.map_err(Error::from_io)?;
+						String::from_utf8_lossy(&command).into_owned()
+					}
+					TemplatedString::No(command) => command.to_string(),
+				};
+
+				if let Err(e) = run_hook(&command, &rendered.path, HOOK_TIMEOUT) {
+					warn!("{}", e);
+				}
+			}
+		} else {
+			apply_permissions(&rendered.path, &self.output)?;
+		}
+
+		Ok(Output {
+			path: rendered.path,
+			changed,
+		})
+	}
+
+	/// Renders the templates with the scheme picked by `#mode`, falling back to
+	/// `is_dark` when the template doesn't set one. `additional` holds the keys
+	/// that don't come from the theme, such as `image`.
+	pub fn render(
+		&self,
+		theme: &MateyTheme,
+		is_dark: bool,
+		additional: &[(String, IndexableVariable)],
+	) -> Fallible<Rendered> {
+		let variables = Variables::new(theme, self.is_dark(is_dark), additional);
+		let scheme = variables.scheme();
+		let palettes = &theme.palettes;
+
+		let path = match &self.outfile {
 			TemplatedString::Yes(template) => {
 				let mut path = Vec::new();
 				template
-					.run_with_hashmap(&mut path, hashmap)
+					.run_with_variables(&mut path, &variables)
 					.map_err(Error::from_io)?;
-				File::create(String::from_utf8(path.clone()).expect("Invalid file path. Aborting"))
-					.map_err(|_| {
-						Error::IO(format!("Could not open file {:?}", unsafe {
-							String::from_utf8_unchecked(path)
-						}))
-					})?
+				let path = String::from_utf8(path)
+					.map_err(|_| Error::config("the output path is not valid UTF-8"))?;
+				PathBuf::from(path)
 			}
-			TemplatedString::No(path) => File::create(path)
-				.map_err(|_| Error::IO(format!("Could not open file {:?}", path)))?,
+			TemplatedString::No(path) => PathBuf::from(path),
 		};
+		let mut file = Vec::new();
 
 		for template in &self.templates {
 			match template {
 				ConfigTemplate::Norm(template) => {
 					template
-						.run_with_hashmap(&mut file, hashmap)
+						.run_with_variables(&mut file, &variables)
+						.map_err(Error::from_io)?;
+				}
+				ConfigTemplate::Foreach(ForeachTarget::Scheme, template) => {
+					template
+						.run_with_scheme(&mut file, scheme, self.rename, &variables)
 						.map_err(Error::from_io)?;
 				}
-				ConfigTemplate::Foreach(template) => {
+				ConfigTemplate::Foreach(ForeachTarget::Dark, template) => {
 					template
-						.run_with_scheme(&mut file, scheme, self.rename)
+						.run_with_scheme(
+							&mut file,
+							&theme.dark,
+							self.rename,
+							&variables.with_mode(true),
+						)
+						.map_err(Error::from_io)?;
+				}
+				ConfigTemplate::Foreach(ForeachTarget::Light, template) => {
+					template
+						.run_with_scheme(
+							&mut file,
+							&theme.light,
+							self.rename,
+							&variables.with_mode(false),
+						)
+						.map_err(Error::from_io)?;
+				}
+				ConfigTemplate::Foreach(ForeachTarget::Palettes, template) => {
+					let colors = palettes
+						.into_iter()
+						.flat_map(|(name, palette)| palette_tones(name, palette));
+					template
+						.run_with_colors(&mut file, colors, self.rename, &variables)
+						.map_err(Error::from_io)?;
+				}
+				ConfigTemplate::Foreach(ForeachTarget::Palette(name), template) => {
+					// the name was checked when parsing
+					let palette = palettes.get(name).unwrap();
+					template
+						.run_with_colors(
+							&mut file,
+							palette_tones(name, palette),
+							self.rename,
+							&variables,
+						)
 						.map_err(Error::from_io)?;
 				}
 			}
 		}
 
-		Ok(())
+		if let Some(region) = &self.region {
+			let existing = match fs::read(&path) {
+				Ok(existing) => existing,
+				Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+				Err(e) => return Err(Error::io("could not read", &path, e)),
+			};
+			file = region
+				.inject(&existing, &file)
+				.map_err(|message| Error::Config {
+					path: Some(path.clone()),
+					message,
+				})?;
+		}
+
+		Ok(Rendered {
+			path,
+			contents: file,
+		})
+	}
+}
+
+/// The part of an existing file that `#inject` replaces, between a start
+/// marker line and an end marker line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Region {
+	start: String,
+	end: String,
+}
+
+impl Region {
+	/// `marker` is either the whole start marker, such as
+	/// `/* >>> matey >>> */`, in which case the end marker is the same with
+	/// `<<<`, or just a comment prefix such as `#` or `--`.
+	fn new(marker: &str) -> Self {
+		let start = if marker.contains(">>>") {
+			marker.trim().to_string()
+		} else {
+			format!("{} >>> matey >>>", marker.trim())
+		};
+
+		Region {
+			end: start.replace(">>>", "<<<"),
+			start,
+		}
+	}
+
+	/// Replaces what is between the markers in `existing` with `rendered`,
+	/// appending the markers at the end if there are none.
+	fn inject(&self, existing: &[u8], rendered: &[u8]) -> Result<Vec<u8>, String> {
+		let lines: Vec<&[u8]> = existing.split_inclusive(|&b| b == b'\n').collect();
+		let find = |marker: &str| {
+			lines
+				.iter()
+				.position(|line| line.trim_ascii() == marker.as_bytes())
+		};
+
+		let (before, after) = match (find(&self.start), find(&self.end)) {
+			(Some(start), Some(end)) if start < end => (&lines[..=start], &lines[end..]),
+			(None, None) => (&lines[..], &[][..]),
+			(Some(_), None) => {
+				return Err(format!(
+					"{:?} is never closed by {:?}",
+					self.start, self.end
+				));
+			}
+			_ => return Err(format!("{:?} comes before {:?}", self.end, self.start)),
+		};
+		let has_markers = !after.is_empty();
+
+		let mut injected = before.concat();
+		let end_line = |buf: &mut Vec<u8>| {
+			if !buf.is_empty() && !buf.ends_with(b"\n") {
+				buf.push(b'\n');
+			}
+		};
+
+		end_line(&mut injected);
+		if !has_markers {
+			injected.extend(self.start.as_bytes());
+			injected.push(b'\n');
+		}
+
+		injected.extend(rendered);
+		end_line(&mut injected);
+
+		if has_markers {
+			injected.extend(after.concat());
+		} else {
+			injected.extend(self.end.as_bytes());
+			injected.push(b'\n');
+		}
+
+		Ok(injected)
+	}
+}
+
+/// The output of a template, before it is written.
+#[derive(Debug)]
+pub struct Rendered {
+	pub path: PathBuf,
+	pub contents: Vec<u8>,
+}
+
+impl Rendered {
+	/// Whether the output file already holds exactly this.
+	pub fn is_up_to_date(&self) -> bool {
+		fs::metadata(&self.path).is_ok_and(|metadata| {
+			metadata.len() == self.contents.len() as u64
+				&& fs::read(&self.path).is_ok_and(|contents| contents == self.contents)
+		})
+	}
+}
+
+/// What [`Config::write`] did to the output file.
+#[derive(Debug)]
+pub struct Output {
+	pub path: PathBuf,
+	/// `false` if the file was left untouched because it was up to date.
+	pub changed: bool,
+}
+
+fn palette_tones<'p>(
+	name: &'p str,
+	palette: &'p MateyPalette,
+) -> impl Iterator<Item = (String, MateyArgb)> + 'p {
+	MateyPalette::COMMON_TONES
+		.into_iter()
+		.filter_map(move |tone| Some((format!("{}_{}", name, tone), palette.tone(tone)?)))
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use crate::{
+		parsers::{config::Region, parse_config, templates::IndexableVariable},
+		test_util::{TempFolder, test_theme},
+	};
+	#[allow(unused_imports)]
+	use std::fs;
+
+	#[test]
+	fn test_region() {
+		let region = Region::new("#");
+		assert_eq!(region.end, "# <<< matey <<<");
+
+		let injected = region.inject(b"font_size 11", b"color0 #000000").unwrap();
+		assert_eq!(
+			injected,
+			b"font_size 11\n# >>> matey >>>\ncolor0 #000000\n# <<< matey <<<\n"
+		);
+
+		let replaced = region
+			.inject(
+				&[&injected[..], b"cursor_blink no\n"].concat(),
+				b"color0 #FFFFFF\n",
+			)
+			.unwrap();
+		assert_eq!(
+			replaced,
+			b"font_size 11\n# >>> matey >>>\ncolor0 #FFFFFF\n# <<< matey <<<\ncursor_blink no\n"
+		);
+
+		let region = Region::new("/* >>> matey >>> */");
+		assert_eq!(region.end, "/* <<< matey <<< */");
+		assert!(
+			region
+				.inject(b"/* >>> matey >>> */\nunclosed\n", b"")
+				.is_err()
+		);
+	}
+
+	#[test]
+	fn test_unchanged() {
+		let folder = TempFolder::new("unchanged");
+		let (out, marker) = (folder.join("out.txt"), folder.join("hooked"));
+		let template = format!(
+			"#out \"{}\"\n#hook \"touch {}\"\nnorm {{{{primary}}}}\n",
+			out.display(),
+			marker.display()
+		);
+		let config = parse_config("test.path", &template).unwrap();
+		let theme = test_theme();
+
+		let output = config.write(&theme, true, &[]).unwrap();
+		assert!(output.changed);
+		assert_eq!(output.path, out);
+		assert!(marker.exists());
+
+		fs::remove_file(&marker).unwrap();
+		let written = fs::metadata(&out).unwrap().modified().unwrap();
+		assert!(config.render(&theme, true, &[]).unwrap().is_up_to_date());
+		let output = config.write(&theme, true, &[]).unwrap();
+		assert!(!output.changed);
+		assert!(!marker.exists());
+		assert_eq!(fs::metadata(&out).unwrap().modified().unwrap(), written);
+
+		// the other mode changes the output again
+		assert!(config.write(&theme, false, &[]).unwrap().changed);
+		assert!(marker.exists());
+	}
+
+	#[test]
+	fn test_hook_quoting() {
+		let folder = TempFolder::new("hook");
+		let out = folder.join("out.txt");
+		let template = format!(
+			"#out \"{}\"\n#hook \"printf '%s|' {{image}} {{mode}} > $MATEY_OUTPUT.hook\"\nnorm {{{{primary}}}}\n",
+			out.display()
+		);
+		let config = parse_config("test.path", &template).unwrap();
+		let theme = test_theme();
+		let image = "it's a $HOME; touch pwned.png";
+		let additional = [("image".to_string(), IndexableVariable::plain(image.into()))];
+
+		config.write(&theme, true, &additional).unwrap();
+		let printed = fs::read_to_string(folder.join("out.txt.hook")).unwrap();
+		assert_eq!(printed, format!("{}|dark|", image));
 	}
 }
@@ -0,0 +1,63 @@
+use crate::material_newtype::{MateyArgb, MateyPalette, MateyPalettes, MateyScheme};
+use crate::parsers::templates::CharIndex;
+
+/// What matey gives to every template besides the colors.
+pub const VARIABLES: [&str; 6] = ["image", "HOME", "CONFIG", "variant", "seed", "mode"];
+
+/// What `foreach` templates know about the color they are on.
+pub const FOREACH_KEYS: [&str; 2] = ["name", "color"];
+
+/// What a known key holds, which decides how it can be indexed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyKind {
+	Color,
+	Plain,
+}
+
+/// Finds what `{key.indexes}` refers to, the way templates resolve dotted
+/// keys, and returns what is left of `indexes` to index it with. `None` if
+/// there is no such key.
+pub fn resolve_global<'i>(key: &str, indexes: &'i str) -> Option<(KeyKind, &'i str)> {
+	if MateyScheme::FIELD_NAMES.contains(&key) || key == "seed" {
+		return Some((KeyKind::Color, indexes));
+	}
+	if VARIABLES.contains(&key) {
+		return Some((KeyKind::Plain, indexes));
+	}
+
+	let (first, rest) = indexes.split_once('.').unwrap_or((indexes, ""));
+	match key {
+		"dark" | "light" if MateyScheme::FIELD_NAMES.contains(&first) => {
+			Some((KeyKind::Color, rest))
+		}
+		"palette" if MateyPalettes::NAMES.contains(&first) => {
+			let (tone, rest) = rest.split_once('.').unwrap_or((rest, ""));
+			MateyPalette::parse_tone(tone).map(|_| (KeyKind::Color, rest))
+		}
+		_ => None,
+	}
+}
+
+/// Checks `indexes` the way they are applied when the template runs, returning
+/// what is wrong with them.
+pub fn check_indexes(kind: KeyKind, indexes: &str) -> Result<(), String> {
+	if indexes.is_empty() {
+		return Ok(());
+	}
+
+	match kind {
+		KeyKind::Plain => Err("only colors can be indexed".to_string()),
+		KeyKind::Color => {
+			// any color does, only the index names matter
+			let color = MateyArgb::default();
+			if color.get_named(indexes).is_some() {
+				return Ok(());
+			}
+
+			match indexes.chars().find(|&index| color.get(index).is_none()) {
+				Some(index) => Err(format!("unknown index {:?}", index)),
+				None => Ok(()),
+			}
+		}
+	}
+}
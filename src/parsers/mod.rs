@@ -2,6 +2,7 @@
 
 mod common;
 mod config;
+mod keys;
 mod parser;
 mod templates;
 mod tokenizer;
@@ -12,25 +13,66 @@ use config::Config;
 use parser::parse_tokens;
 use tokenizer::parse_source;
 
-use crate::error::Fallible;
+use crate::diagnostic::Diagnostic;
+use crate::error::{Error, Fallible};
 
+pub use config::{Output, Rendered};
 pub use templates::{CharIndex, IndexableVariable};
 
 pub fn parse_config<'a>(path: impl AsRef<Path>, source: &'a str) -> Fallible<Config<'a>> {
 	let tokens = parse_source(source, path.as_ref().into());
 
-	parse_tokens(tokens, path.as_ref().into())
+	parse_tokens(tokens, path.as_ref(), source)
+}
+
+/// Every error and warning in a template file, whether it could be used or
+/// not.
+pub fn check_config(path: impl AsRef<Path>, source: &str) -> Vec<Diagnostic> {
+	match parse_config(path, source) {
+		Ok(config) => config.warnings().to_vec(),
+		Err(Error::Parse(diagnostics)) => diagnostics,
+		Err(e) => unreachable!("parsing failed without diagnostics: {}", e),
+	}
 }
 
 mod test {
 	#[allow(unused_imports)]
-	use crate::parsers::parse_config;
+	use crate::{
+		diagnostic::{FileLocation, Severity},
+		error::Error,
+		material_newtype::{MateyArgb, MateyPalette, MateyScheme, MateyTheme, MateyVariant},
+		parsers::{check_config, parse_config, templates::IndexableVariable},
+		test_util::test_theme,
+	};
+	#[allow(unused_imports)]
+	use material_colors::color::Argb;
+
+	#[allow(dead_code)]
+	fn render(template: &str, is_dark: bool) -> String {
+		render_with(template, is_dark, &[])
+	}
+
+	#[allow(dead_code)]
+	fn render_with(
+		template: &str,
+		is_dark: bool,
+		additional: &[(String, IndexableVariable)],
+	) -> String {
+		let config = parse_config("test.path", template).unwrap();
+		let rendered = config.render(&test_theme(), is_dark, additional).unwrap();
+		String::from_utf8(rendered.contents).unwrap()
+	}
 
 	#[test]
 	fn test_template() {
 		let templates = [
 			"#out \"test.out\"\nforeach{{color}={name}}".to_string(),
 			"#out outfile\nforeach{}norm{{image}}".to_string(),
+			"#out outfile\nforeach palette.primary {{name}={color}}\nnorm{{palette.error.40}}"
+				.to_string(),
+			"#out outfile\nforeach dark {{name}={color}}\nnorm{{light.primary.rgb}}".to_string(),
+			"#out outfile\n#mode light\nforeach dark {{name}={color}}\nnorm{{light.primary.rgb}}"
+				.to_string(),
 		];
 		for template in templates {
 			let config = parse_config("test.path", &template);
@@ -38,4 +80,335 @@ mod test {
 			assert!(config.is_ok());
 		}
 	}
+
+	#[test]
+	fn test_variant() {
+		let config = parse_config("test.path", "#out \"x\"\n#variant fruit-salad\n").unwrap();
+		assert_eq!(config.variant(), Some(MateyVariant::FruitSalad));
+
+		let config = parse_config("test.path", "#out \"x\"\n").unwrap();
+		assert_eq!(config.variant(), None);
+
+		match parse_config("test.path", "#out \"x\"\n#variant pastel\n") {
+			Err(Error::Parse(diagnostics)) => {
+				assert_eq!(diagnostics[0].severity, Severity::Error);
+				assert_eq!(diagnostics[0].message, "unknown variant pastel");
+			}
+			other => panic!("expected a diagnostic, got {:?}", other),
+		}
+	}
+
+	#[test]
+	fn test_contrast() {
+		for (value, level) in [("-0.5", -0.5), ("1", 1.0), ("\"0.25\"", 0.25)] {
+			let template = format!("#out \"x\"\n#contrast {}\n", value);
+			let config = parse_config("test.path", &template).unwrap();
+			assert_eq!(config.contrast_level(), Some(level));
+		}
+
+		for value in ["1.5", "-2", "high", "\"\""] {
+			let template = format!("#out \"x\"\n#contrast {}\n", value);
+			match parse_config("test.path", &template) {
+				Err(Error::Parse(diagnostics)) => {
+					assert_eq!(diagnostics.len(), 1);
+					assert_eq!(diagnostics[0].severity, Severity::Error);
+					assert_eq!(
+						diagnostics[0].location,
+						FileLocation {
+							line: 2,
+							column: 11
+						}
+					);
+				}
+				other => panic!("expected a diagnostic for {}, got {:?}", value, other),
+			}
+		}
+	}
+
+	#[test]
+	fn test_diagnostics() {
+		let templates = [
+			("#out \"x\"\n#outfile \"y\"\n", (2, 1), 8),
+			("#out \"x\"\nnorm {\n{primary}\n", (2, 6), 1),
+			("#out \"x\"\n\tforeach bogus {}\n", (2, 10), 5),
+			("#out \"never closed\n", (1, 6), 13),
+		];
+		for (template, (line, column), width) in templates {
+			let diagnostics = match parse_config("test.path", template) {
+				Err(Error::Parse(diagnostics)) => diagnostics,
+				other => panic!("expected a diagnostic, got {:?}", other),
+			};
+			assert_eq!(diagnostics[0].location, FileLocation { line, column });
+			assert_eq!(diagnostics[0].width, width);
+		}
+	}
+
+	#[test]
+	fn test_recovery() {
+		let template = "#outfile \"y\"\n#out \"x\"\n#mode dusk\n\"stray\"\n\
+			norm {{primary}{primry}{palette.error.40}{palette.error.400}}\n\
+			foreach {{name}={color.rgb} {colour|lighten(0.1)} {surface|mix(color, 0.5)}}\n";
+		let diagnostics = match parse_config("test.path", template) {
+			Err(Error::Parse(diagnostics)) => diagnostics,
+			other => panic!("expected diagnostics, got {:?}", other),
+		};
+
+		let found: Vec<_> = diagnostics
+			.iter()
+			.map(|diagnostic| (diagnostic.severity, diagnostic.location.line))
+			.collect();
+		assert_eq!(
+			found,
+			[
+				(Severity::Error, 1),
+				(Severity::Warning, 3),
+				(Severity::Error, 4),
+				(Severity::Warning, 5),
+				(Severity::Warning, 5),
+				(Severity::Warning, 6),
+			]
+		);
+
+		// without the errors, the warnings stay with the config
+		let template = template
+			.replacen("#outfile \"y\"\n", "", 1)
+			.replacen("\"stray\"\n", "", 1);
+		let config = parse_config("test.path", &template).unwrap();
+		assert_eq!(config.warnings().len(), 4);
+	}
+
+	#[test]
+	fn test_check() {
+		let template = "#out \"~/out\"\n\
+			norm {{primary.rgb}{primary.RGBA}{palette.error.40.hex}{primary.rgbx}{image.r}{mode|lighten(0.1)}}\n\
+			foreach {{name}{color.hex}{name.r}{color.q}{surface|mix(color, 0.5)}}\n";
+		let found: Vec<_> = check_config("test.path", template)
+			.iter()
+			.map(|diagnostic| (diagnostic.location.line, diagnostic.message.clone()))
+			.collect();
+		assert_eq!(
+			found,
+			[
+				(1, "~ is not expanded in output paths".to_string()),
+				(2, "unknown index 'x'".to_string()),
+				(2, "only colors can be indexed".to_string()),
+				(
+					2,
+					"mode is not a color, filters can't be applied".to_string()
+				),
+				(3, "only colors can be indexed".to_string()),
+				(3, "unknown index 'q'".to_string()),
+			]
+		);
+
+		assert_eq!(check_config("test.path", "#out \"\"\n").len(), 1);
+
+		// keys and text next to characters longer than a byte
+		let found: Vec<_> = check_config("test.path", "#out \"x\"\nnorm {é{primary}ü{é}→}\n")
+			.into_iter()
+			.map(|diagnostic| diagnostic.message)
+			.collect();
+		assert_eq!(found, ["unknown key é"]);
+	}
+
+	#[test]
+	fn test_palettes() {
+		let theme = test_theme();
+		let primary = theme.palettes.get("primary").unwrap();
+		let hex = |palette: &MateyPalette, tone| palette.tone(tone).unwrap().to_hex();
+
+		assert_eq!(
+			render(
+				"#out \"x\"\nnorm {{palette.primary.40}|{palette.primary.040}|{palette.primary.101}}\n",
+				true
+			),
+			format!("{}||", hex(primary, 40))
+		);
+
+		let rendered = render("#out \"x\"\nforeach palette {{name}={color};}\n", true);
+		let expected: String = theme
+			.palettes
+			.into_iter()
+			.flat_map(|(name, palette)| {
+				MateyPalette::COMMON_TONES
+					.map(|tone| format!("{}_{}={};", name, tone, hex(palette, tone)))
+			})
+			.collect();
+		assert_eq!(rendered, expected);
+		assert!(rendered.starts_with(&format!("primary_0={};", hex(primary, 0))));
+	}
+
+	#[test]
+	fn test_schemes() {
+		let theme = test_theme();
+		let names = |scheme: &MateyScheme| -> String {
+			scheme
+				.into_iter()
+				.map(|(name, color)| format!("{}={};", name, color.to_hex()))
+				.collect()
+		};
+
+		for is_dark in [true, false] {
+			assert_eq!(
+				render(
+					"#out \"x\"\nnorm {{dark.primary} {light.primary} {dark.surface.r}}\n",
+					is_dark
+				),
+				format!(
+					"{} {} {}",
+					theme.dark.primary.to_hex(),
+					theme.light.primary.to_hex(),
+					&theme.dark.surface.to_hex()[..2]
+				)
+			);
+			assert_eq!(
+				render("#out \"x\"\nforeach dark {{name}={color};}\n", is_dark),
+				names(&theme.dark)
+			);
+			assert_eq!(
+				render("#out \"x\"\nforeach light {{name}={color};}\n", is_dark),
+				names(&theme.light)
+			);
+		}
+	}
+
+	#[test]
+	fn test_mode() {
+		let theme = test_theme();
+		let (dark, light) = (theme.dark.primary.to_hex(), theme.light.primary.to_hex());
+
+		for (mode, is_dark, picked) in [
+			("dark", false, "dark"),
+			("dark", true, "dark"),
+			("light", false, "light"),
+			("light", true, "light"),
+			("auto", false, "light"),
+			("auto", true, "dark"),
+		] {
+			let template = format!(
+				"#out \"x\"\n#mode {}\nnorm {{{{primary}} {{mode}}}}\n",
+				mode
+			);
+			let primary = if picked == "dark" { &dark } else { &light };
+			assert_eq!(
+				render(&template, is_dark),
+				format!("{} {}", primary, picked)
+			);
+		}
+
+		// without #mode the command line picks
+		assert_eq!(render("#out \"x\"\nnorm {{primary}}\n", false), light);
+	}
+
+	#[test]
+	fn test_seed() {
+		let seed: Argb = "#3366ff".parse().unwrap();
+		let theme = MateyTheme::from_source(seed, MateyVariant::TonalSpot, 0.0);
+		let config = parse_config(
+			"test.path",
+			"#out \"x\"\nnorm {{seed} {seed.r} {primary}}\n",
+		)
+		.unwrap();
+		let rendered = config.render(&theme, true, &[]).unwrap();
+		assert_eq!(
+			String::from_utf8(rendered.contents).unwrap(),
+			format!("3366FF 33 {}", theme.dark.primary.to_hex())
+		);
+		assert!(!config.uses_key("image"));
+
+		// the seed is the source, so the theme only depends on it
+		let other =
+			MateyTheme::from_source("#ff6633".parse().unwrap(), MateyVariant::TonalSpot, 0.0);
+		assert_eq!(theme.source, seed.into());
+		assert_ne!(theme.dark.primary, other.dark.primary);
+
+		let config = parse_config("test.path", "#out \"{image}.out\"\nnorm {{seed}}\n").unwrap();
+		assert!(config.uses_key("image"));
+		let config = parse_config("test.path", "#out \"x\"\n#hook \"echo {image}\"\n").unwrap();
+		assert!(config.uses_key("image"));
+	}
+
+	#[test]
+	fn test_conditions() {
+		let image = [(
+			"image".to_string(),
+			IndexableVariable::plain(b"wall.png".to_vec()),
+		)];
+		let norm = "#out \"x\"\nnorm {{if dark}d{else}l{end}{if variant == tonal_spot}=={end}{if mode != 'dark'}!={end}}\n";
+		assert_eq!(render(norm, true), "d==");
+		assert_eq!(render(norm, false), "l==!=");
+
+		let nested =
+			"#out \"x\"\nnorm {{if image}{if not light}{image}{else}light{end}{else}none{end}}\n";
+		assert_eq!(render_with(nested, true, &image), "wall.png");
+		assert_eq!(render_with(nested, false, &image), "light");
+		assert_eq!(render(nested, true), "none");
+
+		// foreach dark and light set the mode, other keys come from the template
+		let foreach = |target| {
+			format!(
+				"#out \"x\"\nforeach {} {{{{if name == 'primary'}}{{if dark}}d{{else}}l{{end}}{{if image}}i{{end}}{{end}}}}\n",
+				target
+			)
+		};
+		assert_eq!(render_with(&foreach("dark"), false, &image), "di");
+		assert_eq!(render_with(&foreach("light"), true, &image), "li");
+		assert_eq!(render(&foreach(""), true), "d");
+		assert_eq!(render(&foreach(""), false), "l");
+	}
+
+	#[test]
+	fn test_filters() {
+		let grey = [(
+			"grey".to_string(),
+			MateyArgb::from(Argb::from_u32(0xff808080)).into(),
+		)];
+		let filtered = |filters: &str| {
+			let template = format!("#out \"x\"\nnorm {{{{{}}}}}\n", filters);
+			render_with(&template, true, &grey)
+		};
+		let is_grey = |hex: &str| hex[0..2] == hex[2..4] && hex[2..4] == hex[4..6];
+		let primary = render("#out \"x\"\nnorm {{primary}}\n", true);
+
+		assert_eq!(filtered("grey | lighten(100)"), "FFFFFF");
+		assert_eq!(filtered("grey | darken(100)"), "000000");
+		assert_eq!(filtered("grey | set_tone(100)"), "FFFFFF");
+		assert_eq!(filtered("grey | set-tone(0)"), "000000");
+		assert!(!is_grey(&filtered("grey | saturate(30)")));
+		assert!(is_grey(&filtered("primary | desaturate(200)")));
+		assert_eq!(filtered("grey | invert"), "7F7F7F");
+		assert_eq!(filtered("grey | mix(#000000, 0.5)"), "404040");
+		assert_eq!(filtered("grey | mix(primary, 1)"), primary);
+
+		// the alpha is printed once a filter changed it
+		assert_eq!(filtered("grey | alpha(0.5)"), "80808080");
+		assert_eq!(filtered("grey | alpha(1)"), "808080");
+		assert_eq!(filtered("grey.a | alpha(0.5)"), "80");
+
+		assert_eq!(filtered("grey | invert | alpha(0.5)"), "7F7F7F80");
+		assert_eq!(
+			filtered("grey | set_tone(100) | mix(#000000, 0.5)"),
+			"808080"
+		);
+		assert_eq!(filtered("grey | darken(100) | invert"), "FFFFFF");
+
+		// invalid filters are left out, the others still apply
+		for (filters, invalid) in [
+			("grey | lighten(x) | invert", "lighten(x)"),
+			("grey | blur(2) | invert", "blur(2)"),
+			("grey | mix(#000000) | invert", "mix(#000000)"),
+		] {
+			assert_eq!(filtered(filters), "7F7F7F");
+			let template = format!("#out \"x\"\nnorm {{{{{}}}}}\n", filters);
+			let config = parse_config("test.path", &template).unwrap();
+			let message = format!("invalid filter {}", invalid);
+			assert!(
+				config
+					.warnings()
+					.iter()
+					.any(|warning| warning.message == message)
+			);
+		}
+		assert_eq!(filtered("grey | mix(nothing, 0.5)"), "808080");
+	}
 }
@@ -1,148 +1,483 @@
-use std::ffi::OsString;
 use std::iter::Peekable;
+use std::path::Path;
 
+use crate::diagnostic::{Diagnostic, Severity, Span};
 use crate::error::{Error, Fallible};
+use crate::material_newtype::MateyVariant;
 use crate::parsers::config::Config;
-use crate::parsers::config::ConfigBuilder;
+use crate::parsers::config::{ConfigBuilder, ForeachTarget};
 
+use super::keys::{FOREACH_KEYS, KeyKind, check_indexes, resolve_global};
 use super::templates::Template;
 use super::tokenizer::{ConfigToken, Token};
 
-fn parse_error(filename: &OsString, token: &Token, message: String) -> Error {
-	Error::parse_error(format!(
-		"({:#?} at {}) {}",
-		filename, token.location, message
-	))
+const COMMANDS: &[&str] = &[
+	"out",
+	"naming",
+	"variant",
+	"contrast",
+	"mode",
+	"backup",
+	"permissions",
+	"inject",
+	"hook",
+	"after",
+];
+
+/// Collects the diagnostics of a file, so that a mistake doesn't hide the
+/// ones after it.
+struct Reporter<'a> {
+	path: &'a Path,
+	source: &'a str,
+	diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Reporter<'a> {
+	fn error(&self, span: Span, message: String, hint: Option<String>) -> Box<Diagnostic> {
+		let mut diagnostic = Diagnostic::error(self.path, self.source, span, message);
+		if let Some(hint) = hint {
+			diagnostic = diagnostic.with_hint(hint);
+		}
+		Box::new(diagnostic)
+	}
+
+	fn at_token(&self, token: &Token, message: String, hint: Option<String>) -> Box<Diagnostic> {
+		self.error(token.span, message, hint)
+	}
+
+	/// For errors found right after `token`.
+	fn after_token(&self, token: &Token, message: String, hint: Option<String>) -> Box<Diagnostic> {
+		self.error(Span::new(token.span.end, token.span.end), message, hint)
+	}
+
+	fn warn(&mut self, span: Span, message: String, hint: Option<&str>) {
+		let mut diagnostic = Diagnostic::warning(self.path, self.source, span, message);
+		if let Some(hint) = hint {
+			diagnostic = diagnostic.with_hint(hint);
+		}
+		self.diagnostics.push(diagnostic);
+	}
+
+	/// Where `snippet` is in the source, or `None` if it isn't a slice of it.
+	fn span_of(&self, snippet: &str) -> Option<Span> {
+		let source = self.source.as_bytes().as_ptr_range();
+		let range = snippet.as_bytes().as_ptr_range();
+		if range.start < source.start || range.end > source.end {
+			return None;
+		}
+
+		let start = range.start as usize - source.start as usize;
+		Some(Span::new(start, start + snippet.len()))
+	}
+}
+
+/// What the keys of a template can refer to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyScope {
+	/// `norm` templates, `#out` and `#hook`
+	Global,
+	Foreach,
+}
+
+/// Reports the mistakes `Template::new` found and the keys nothing will be
+/// given for, as warnings since the rest of the template still works.
+/// `within` is the span of the whole template, used if a part of it can't be
+/// found in the source.
+fn check_template(reporter: &mut Reporter, template: &Template, scope: KeyScope, within: Span) {
+	let span_of = |reporter: &Reporter, snippet| reporter.span_of(snippet).unwrap_or(within);
+
+	for (snippet, message) in template.warnings() {
+		reporter.warn(span_of(reporter, snippet), message.clone(), None);
+	}
+
+	for key in template.keys() {
+		let resolved = match scope {
+			KeyScope::Foreach if key.key == "color" => Some((KeyKind::Color, key.indexes)),
+			KeyScope::Foreach if key.key == "name" => Some((KeyKind::Plain, key.indexes)),
+			// filtered keys fall back to the other colors
+			KeyScope::Foreach if !key.filtered => None,
+			_ => resolve_global(key.key, key.indexes),
+		};
+
+		let (mut span, mut written) = (span_of(reporter, key.key), key.key.to_string());
+		if !key.indexes.is_empty() {
+			span.end = span_of(reporter, key.indexes).end.max(span.end);
+			written = format!("{}.{}", key.key, key.indexes);
+		}
+
+		let Some((kind, indexes)) = resolved else {
+			let hint = match scope {
+				KeyScope::Foreach => {
+					format!("foreach templates know {}", FOREACH_KEYS.join(" and "))
+				}
+				KeyScope::Global => {
+					"keys are scheme colors such as primary, dark.primary, palette.primary.40, or image, HOME, CONFIG, variant, seed and mode"
+						.to_string()
+				}
+			};
+			reporter.warn(span, format!("unknown key {}", written), Some(&hint));
+			continue;
+		};
+
+		if key.filtered && kind == KeyKind::Plain {
+			reporter.warn(
+				span,
+				format!("{} is not a color, filters can't be applied", written),
+				None,
+			);
+		} else if let Err(message) = check_indexes(kind, indexes) {
+			let hint = (kind == KeyKind::Color).then_some(
+				"colors take r, g, b and a, in uppercase for decimal, or one of hex, hex_lower, hex_argb, css_rgb, css_rgba, hsl, hsla, float and float_alpha",
+			);
+			reporter.warn(span_of(reporter, indexes), message, hint);
+		}
+	}
 }
 
-fn expect_token<'a, 'b, I>(tokens: &mut Peekable<I>, token: ConfigToken<'b>) -> bool
+/// Skips what is left of the current line, to carry on after an error.
+fn skip_line<'a, I>(tokens: &mut Peekable<I>)
 where
-	I: Iterator<Item = Fallible<ConfigToken<'a>>>,
+	I: Iterator<Item = Result<ConfigToken<'a>, Box<Diagnostic>>>,
 {
-	tokens
-		.next_if(|tt| tt.is_ok() && *tt.as_ref().unwrap() == token)
-		.is_some()
+	for token in tokens.by_ref() {
+		if matches!(token, Ok(ConfigToken::NewLine | ConfigToken::Eof)) {
+			break;
+		}
+	}
 }
 
-pub fn parse_tokens<'a, I>(tokens: I, filename: OsString) -> Fallible<Config<'a>>
+/// The error in the next token, consuming it.
+fn take_error<'a, I>(tokens: &mut Peekable<I>) -> Box<Diagnostic>
 where
-	I: IntoIterator<Item = Fallible<ConfigToken<'a>>>,
+	I: Iterator<Item = Result<ConfigToken<'a>, Box<Diagnostic>>>,
 {
-	let mut iter = tokens.into_iter().peekable();
+	match tokens.next() {
+		Some(Err(diagnostic)) => diagnostic,
+		_ => unreachable!(),
+	}
+}
 
-	let mut config_builder = ConfigBuilder::new();
-	while let Some(token) = iter.peek() {
-		let token = match token {
-			Ok(token) => *token,
-			Err(e) => return Err(e.clone()),
-		};
+/// Checks that nothing comes after a command, without consuming the token
+/// that does.
+fn expect_line_end<'a, I>(
+	tokens: &mut Peekable<I>,
+	reporter: &Reporter,
+	command: &Token,
+) -> Result<(), Box<Diagnostic>>
+where
+	I: Iterator<Item = Result<ConfigToken<'a>, Box<Diagnostic>>>,
+{
+	match tokens.peek() {
+		None | Some(Ok(ConfigToken::NewLine | ConfigToken::Eof)) => {
+			tokens.next();
+			Ok(())
+		}
+		Some(Err(_)) => Err(take_error(tokens)),
+		Some(Ok(
+			ConfigToken::OptionCommand(token)
+			| ConfigToken::Id(token)
+			| ConfigToken::Number(token)
+			| ConfigToken::Literal(token)
+			| ConfigToken::TemplateBlock(token),
+		)) => Err(reporter.at_token(
+			token,
+			format!("expected a new line after #{}", command.source),
+			Some("commands take a single value".to_string()),
+		)),
+	}
+}
 
-		match token {
-			ConfigToken::OptionCommand(command) => {
-				// FIXME: this is temporary
-				iter.next();
-
-				let mut is_template = false;
-				let arg = match iter.next() {
-					Some(Ok(ConfigToken::Literal(arg))) => arg.source,
-					Some(Ok(ConfigToken::TemplateBlock(arg))) => {
-						is_template = true;
-						arg.source
-					}
-					t => {
-						println!("got {:?}", t);
-						return parse_error(
-							&filename,
-							&command,
-							"expected literal after command token".to_string(),
-						)
-						.into();
-					}
-				};
-
-				match command.source {
-					"out" => {
-						if is_template {
-							config_builder.set_outfile_template(Template::new(arg));
-						} else {
-							config_builder.set_outfile(arg);
-						}
-					}
-					"naming" => {
-						config_builder.set_naming(arg);
-					}
-					unknown => {
-						return parse_error(
-							&filename,
-							&command,
-							format!("unknown command {}", unknown),
-						)
-						.into();
-					}
-				}
+fn parse_command<'a, I>(
+	tokens: &mut Peekable<I>,
+	config_builder: &mut ConfigBuilder<'a>,
+	reporter: &mut Reporter,
+) -> Result<(), Box<Diagnostic>>
+where
+	I: Iterator<Item = Result<ConfigToken<'a>, Box<Diagnostic>>>,
+{
+	let Some(Ok(ConfigToken::OptionCommand(command))) = tokens.next() else {
+		unreachable!();
+	};
 
-				if !expect_token(&mut iter, ConfigToken::Eof)
-					&& !expect_token(&mut iter, ConfigToken::NewLine)
-				{
-					return parse_error(
-						&filename,
-						&command,
-						format!("expected newline after command got {:?}", iter.next()),
-					)
-					.into();
-				}
+	let (arg, is_template) = match tokens.peek() {
+		Some(Ok(ConfigToken::Literal(arg) | ConfigToken::Id(arg) | ConfigToken::Number(arg))) => {
+			(*arg, false)
+		}
+		Some(Ok(ConfigToken::TemplateBlock(arg))) => (*arg, true),
+		Some(Err(_)) => return Err(take_error(tokens)),
+		_ => {
+			return Err(reporter.after_token(
+				&command,
+				format!("expected a value after #{}", command.source),
+				Some(format!("write it as `#{} \"value\"`", command.source)),
+			));
+		}
+	};
+	tokens.next();
+
+	let template = |reporter: &mut Reporter| {
+		let template = Template::new(arg.source);
+		check_template(reporter, &template, KeyScope::Global, arg.span);
+		template
+	};
+	if command.source == "out" && arg.source.starts_with('~') {
+		reporter.warn(
+			arg.span,
+			"~ is not expanded in output paths".to_string(),
+			Some("use {HOME} instead"),
+		);
+	}
+	let set = match command.source {
+		"out" if is_template => {
+			config_builder.set_outfile_template(template(reporter));
+			Ok(())
+		}
+		"out" if arg.source.trim().is_empty() => {
+			return Err(reporter.at_token(&arg, "the output path is empty".to_string(), None));
+		}
+		"out" => {
+			config_builder.set_outfile(arg.source);
+			Ok(())
+		}
+		"naming" => config_builder.set_naming(arg.source),
+		// unlike the other settings, a wrong variant or contrast gives another
+		// theme altogether, so it isn't replaced by the default
+		"variant" => {
+			if let Err(message) = config_builder.set_variant(arg.source) {
+				let names: Vec<_> = MateyVariant::ALL.iter().map(MateyVariant::name).collect();
+				return Err(reporter.at_token(
+					&arg,
+					message,
+					Some(format!("the variants are {}", names.join(", "))),
+				));
 			}
-			ConfigToken::Id(name) => {
-				iter.next();
-
-				let template = match iter.next() {
-					Some(Ok(ConfigToken::TemplateBlock(template))) => template,
-					Some(Err(e)) => return Err(e),
-					None | Some(Ok(_)) => {
-						return parse_error(
-							&filename,
-							&name,
-							format!("expected template after id {}", name.source),
-						)
-						.into();
-					}
-				};
-
-				match name.source {
-					"foreach" => {
-						config_builder.add_foreach_template(Template::new(template.source))
-					}
-					"norm" => config_builder.add_norm_template(Template::new(template.source)),
-					_ => {
-						return parse_error(
-							&filename,
-							&name,
-							format!("unsupported template type {}", name.source),
-						)
-						.into();
-					}
-				}
+			Ok(())
+		}
+		"contrast" => {
+			if let Err(message) = config_builder.set_contrast(arg.source) {
+				return Err(reporter.at_token(
+					&arg,
+					message,
+					Some(
+						"-1.0 is reduced contrast, 0.0 the standard one and 1.0 high contrast"
+							.to_string(),
+					),
+				));
 			}
-			ConfigToken::Number(_token) => todo!(),
-			ConfigToken::TemplateBlock(token) => {
-				return parse_error(
-					&filename,
-					&token,
-					"Unnamed templates aren't allowed".to_string(),
-				)
-				.into();
+			Ok(())
+		}
+		"mode" => config_builder.set_mode(arg.source),
+		"backup" => config_builder.set_backup(arg.source),
+		"permissions" => config_builder.set_permissions(arg.source),
+		"inject" => {
+			config_builder.set_region(arg.source);
+			Ok(())
+		}
+		"hook" | "after" if is_template => {
+			config_builder.add_hook_template(template(reporter));
+			Ok(())
+		}
+		"hook" | "after" => {
+			config_builder.add_hook(arg.source);
+			Ok(())
+		}
+		unknown => {
+			return Err(reporter.at_token(
+				&command,
+				format!("unknown command #{}", unknown),
+				Some(format!("the commands are {}", COMMANDS.join(", "))),
+			));
+		}
+	};
+	if let Err(message) = set {
+		reporter.warn(arg.span, message, Some("the default is used instead"));
+	}
+
+	expect_line_end(tokens, reporter, &command)
+}
+
+fn parse_template<'a, I>(
+	tokens: &mut Peekable<I>,
+	config_builder: &mut ConfigBuilder<'a>,
+	reporter: &mut Reporter,
+) -> Result<(), Box<Diagnostic>>
+where
+	I: Iterator<Item = Result<ConfigToken<'a>, Box<Diagnostic>>>,
+{
+	let Some(Ok(ConfigToken::Id(name))) = tokens.next() else {
+		unreachable!();
+	};
+
+	let mut target = ForeachTarget::Scheme;
+	if let Some(Ok(ConfigToken::Id(selector))) = tokens.peek() {
+		let selector = *selector;
+		tokens.next();
+
+		if name.source != "foreach" {
+			return Err(reporter.at_token(
+				&selector,
+				format!("{} templates don't take a selector", name.source),
+				None,
+			));
+		}
+
+		target = match ForeachTarget::from_selector(selector.source) {
+			Some(target) => target,
+			None => {
+				return Err(reporter.at_token(
+					&selector,
+					format!("unknown foreach selector {}", selector.source),
+					Some("use dark, light, palette or palette.NAME".to_string()),
+				));
 			}
-			ConfigToken::NewLine => {
-				iter.next();
+		};
+	}
+
+	let block = match tokens.peek() {
+		Some(Ok(ConfigToken::TemplateBlock(block))) => *block,
+		Some(Err(_)) => return Err(take_error(tokens)),
+		None | Some(Ok(_)) => {
+			return Err(reporter.after_token(
+				&name,
+				format!("expected a template after {}", name.source),
+				Some(format!("templates look like `{} {{ ... }}`", name.source)),
+			));
+		}
+	};
+	tokens.next();
+
+	let scope = match name.source {
+		"foreach" => KeyScope::Foreach,
+		"norm" => KeyScope::Global,
+		_ => {
+			return Err(reporter.at_token(
+				&name,
+				format!("unsupported template type {}", name.source),
+				Some("templates are either foreach or norm".to_string()),
+			));
+		}
+	};
+
+	let template = Template::new(block.source);
+	check_template(reporter, &template, scope, block.span);
+	match scope {
+		KeyScope::Foreach => config_builder.add_foreach_template(target, template),
+		KeyScope::Global => config_builder.add_norm_template(template),
+	}
+
+	Ok(())
+}
+
+/// Parses the whole file, carrying on with the next line after an error.
+/// Every error and warning is returned if there was an error, otherwise the
+/// warnings are kept in the config.
+pub fn parse_tokens<'a, I>(tokens: I, path: &Path, source: &str) -> Fallible<Config<'a>>
+where
+	I: IntoIterator<Item = Result<ConfigToken<'a>, Box<Diagnostic>>>,
+{
+	let mut reporter = Reporter {
+		path,
+		source,
+		diagnostics: Vec::new(),
+	};
+	let mut tokens = tokens.into_iter().peekable();
+
+	let mut config_builder = ConfigBuilder::new();
+	while let Some(token) = tokens.peek() {
+		let parsed = match token {
+			Err(_) => Err(take_error(&mut tokens)),
+			Ok(ConfigToken::NewLine) => {
+				tokens.next();
+				continue;
 			}
-			ConfigToken::Eof => {
-				break;
+			Ok(ConfigToken::Eof) => break,
+			Ok(ConfigToken::OptionCommand(_)) => {
+				parse_command(&mut tokens, &mut config_builder, &mut reporter)
 			}
-			ConfigToken::Literal(token) => {
-				return parse_error(&filename, &token, "Unexpected string".to_string()).into();
+			Ok(ConfigToken::Id(_)) => {
+				parse_template(&mut tokens, &mut config_builder, &mut reporter)
 			}
+			Ok(ConfigToken::TemplateBlock(token)) => Err(reporter.at_token(
+				token,
+				"unnamed templates aren't allowed".to_string(),
+				Some("name it, as in `norm { ... }` or `foreach { ... }`".to_string()),
+			)),
+			Ok(ConfigToken::Literal(token) | ConfigToken::Number(token)) => Err(reporter.at_token(
+				token,
+				"unexpected value".to_string(),
+				Some("values only go after a command, as in `#out \"path\"`".to_string()),
+			)),
+		};
+
+		if let Err(diagnostic) = parsed {
+			reporter.diagnostics.push(*diagnostic);
+			skip_line(&mut tokens);
 		}
 	}
 
-	Ok(config_builder.build()?)
+	let failed = reporter
+		.diagnostics
+		.iter()
+		.any(|diagnostic| diagnostic.severity == Severity::Error);
+
+	// the only thing build checks is that there is an output file, which may
+	// well be on a line that had an error
+	let config = match config_builder.build() {
+		Ok(config) => Some(config),
+		Err(Error::Config { .. }) if failed => None,
+		Err(Error::Config { message, .. }) => {
+			let diagnostic = reporter.error(
+				Span::new(0, 0),
+				message.to_lowercase(),
+				Some("add `#out \"path/to/output\"`".to_string()),
+			);
+			reporter.diagnostics.push(*diagnostic);
+			None
+		}
+		Err(e) => return Err(e),
+	};
+
+	let mut diagnostics = reporter.diagnostics;
+	diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+
+	match config {
+		Some(mut config) if !failed => {
+			config.set_warnings(diagnostics);
+			Ok(config)
+		}
+		_ => Err(Error::Parse(diagnostics)),
+	}
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use crate::{diagnostic::Span, parsers::parser::Reporter};
+	#[allow(unused_imports)]
+	use std::path::Path;
+
+	#[test]
+	fn test_span_of() {
+		let source = "#out \"x\"\nnorm {{primary}}\n";
+		let reporter = Reporter {
+			path: Path::new("test.path"),
+			source,
+			diagnostics: Vec::new(),
+		};
+
+		assert_eq!(reporter.span_of(&source[16..23]), Some(Span::new(16, 23)));
+		assert_eq!(
+			reporter.span_of(&source[source.len()..]),
+			Some(Span::new(source.len(), source.len()))
+		);
+		let copy = String::from(&source[16..23]);
+		assert_eq!(reporter.span_of(copy.as_str()), None);
+
+		// a slice that starts inside the source but ends after it
+		let longer = format!("{}more", source);
+		let reporter = Reporter {
+			source: &longer[..source.len()],
+			..reporter
+		};
+		assert_eq!(reporter.span_of(&longer[20..]), None);
+	}
 }
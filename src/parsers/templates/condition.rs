@@ -0,0 +1,87 @@
+#[derive(Debug, PartialEq, Eq)]
+pub enum Operand<'a> {
+	Key(&'a str),
+	Literal(&'a str),
+}
+
+impl<'a> Operand<'a> {
+	fn parse(source: &'a str) -> Self {
+		let source = source.trim();
+		for quote in ['"', '\''] {
+			if let Some(literal) = source
+				.strip_prefix(quote)
+				.and_then(|s| s.strip_suffix(quote))
+			{
+				return Operand::Literal(literal);
+			}
+		}
+
+		Operand::Key(source)
+	}
+
+	/// Keys that can't be found are compared as if they were literals, so
+	/// `{if variant == vibrant}` works without quotes.
+	fn value(&self, lookup: &impl Fn(&str) -> Option<Vec<u8>>) -> Vec<u8> {
+		match self {
+			Operand::Key(key) => lookup(key).unwrap_or_else(|| key.as_bytes().to_vec()),
+			Operand::Literal(literal) => literal.as_bytes().to_vec(),
+		}
+	}
+}
+
+/// The condition of an `{if ...}` block:
+/// - `{if dark}` and `{if light}` test the mode of the scheme
+/// - `{if key}` tests that the key exists and isn't empty
+/// - `{if a == b}` and `{if a != b}` compare what `{a}` and `{b}` would print
+/// - `{if not cond}` or `{if !cond}` negates the condition
+#[derive(Debug, PartialEq, Eq)]
+pub enum Condition<'a> {
+	Defined(&'a str),
+	Equals(Operand<'a>, Operand<'a>),
+	Not(Box<Condition<'a>>),
+}
+
+impl<'a> Condition<'a> {
+	pub fn parse(source: &'a str) -> Option<Self> {
+		let source = source.trim();
+		if source.is_empty() {
+			return None;
+		}
+
+		if let Some(rest) = source
+			.strip_prefix("not ")
+			.or_else(|| source.strip_prefix('!'))
+		{
+			return Some(Condition::Not(Box::new(Condition::parse(rest)?)));
+		}
+
+		if let Some((left, right)) = source.split_once("!=") {
+			return Some(Condition::Not(Box::new(Condition::Equals(
+				Operand::parse(left),
+				Operand::parse(right),
+			))));
+		}
+
+		if let Some((left, right)) = source.split_once("==") {
+			return Some(Condition::Equals(
+				Operand::parse(left),
+				Operand::parse(right),
+			));
+		}
+
+		Some(match source {
+			"dark" | "light" => Condition::Equals(Operand::Key("mode"), Operand::Literal(source)),
+			key => Condition::Defined(key),
+		})
+	}
+
+	/// `lookup` gives what `{key}` would print, or `None` if the key doesn't
+	/// exist.
+	pub fn eval(&self, lookup: &impl Fn(&str) -> Option<Vec<u8>>) -> bool {
+		match self {
+			Condition::Defined(key) => lookup(key).is_some_and(|value| !value.is_empty()),
+			Condition::Equals(left, right) => left.value(lookup) == right.value(lookup),
+			Condition::Not(condition) => !condition.eval(lookup),
+		}
+	}
+}
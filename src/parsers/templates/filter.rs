@@ -0,0 +1,103 @@
+use material_colors::color::Argb;
+use material_colors::hct::Hct;
+
+use crate::material_newtype::MateyArgb;
+
+/// A color transformation applied with `{key | filter(args)}`. Tones and
+/// chroma are the ones of the HCT color space, tones going from 0 to 100.
+/// A result that isn't opaque is printed as RRGGBBAA.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter<'a> {
+	Lighten(f64),
+	Darken(f64),
+	Saturate(f64),
+	Desaturate(f64),
+	SetTone(f64),
+	Alpha(f64),
+	Mix(&'a str, f64),
+	Invert,
+}
+
+impl<'a> Filter<'a> {
+	pub fn parse(source: &'a str) -> Option<Self> {
+		let source = source.trim();
+		let (name, args) = match source.split_once('(') {
+			Some((name, args)) => (name.trim(), args.strip_suffix(')')?),
+			None => (source, ""),
+		};
+		let args: Vec<&str> = args
+			.split(',')
+			.map(str::trim)
+			.filter(|arg| !arg.is_empty())
+			.collect();
+		let number = |i: usize| args.get(i)?.parse::<f64>().ok();
+
+		Some(match (name, args.len()) {
+			("lighten", 1) => Filter::Lighten(number(0)?),
+			("darken", 1) => Filter::Darken(number(0)?),
+			("saturate", 1) => Filter::Saturate(number(0)?),
+			("desaturate", 1) => Filter::Desaturate(number(0)?),
+			("set-tone" | "set_tone", 1) => Filter::SetTone(number(0)?),
+			("alpha", 1) => Filter::Alpha(number(0)?.clamp(0.0, 1.0)),
+			("mix", 2) => Filter::Mix(args[0], number(1)?.clamp(0.0, 1.0)),
+			("invert", 0) => Filter::Invert,
+			_ => return None,
+		})
+	}
+
+	/// `lookup` resolves the color given to `mix`, which can either be a key
+	/// or a `#RRGGBB` literal.
+	pub fn apply(
+		&self,
+		color: MateyArgb,
+		lookup: &impl Fn(&str) -> Option<MateyArgb>,
+	) -> Option<MateyArgb> {
+		let with_hct = |f: &dyn Fn(&mut Hct)| {
+			let mut hct = Hct::new(color.into());
+			f(&mut hct);
+			MateyArgb {
+				alpha: color.alpha,
+				..Argb::from(hct).into()
+			}
+		};
+
+		Some(match *self {
+			Filter::Lighten(amount) => {
+				with_hct(&|hct| hct.set_tone((hct.get_tone() + amount).clamp(0.0, 100.0)))
+			}
+			Filter::Darken(amount) => {
+				with_hct(&|hct| hct.set_tone((hct.get_tone() - amount).clamp(0.0, 100.0)))
+			}
+			Filter::Saturate(amount) => {
+				with_hct(&|hct| hct.set_chroma((hct.get_chroma() + amount).max(0.0)))
+			}
+			Filter::Desaturate(amount) => {
+				with_hct(&|hct| hct.set_chroma((hct.get_chroma() - amount).max(0.0)))
+			}
+			Filter::SetTone(tone) => with_hct(&|hct| hct.set_tone(tone.clamp(0.0, 100.0))),
+			Filter::Alpha(alpha) => MateyArgb {
+				alpha: (alpha * 255.0).round() as u8,
+				..color
+			},
+			Filter::Mix(other, amount) => {
+				let other = match lookup(other) {
+					Some(other) => other,
+					None => MateyArgb::from(other.parse::<Argb>().ok()?),
+				};
+				let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * amount).round() as u8;
+				MateyArgb {
+					alpha: mix(color.alpha, other.alpha),
+					red: mix(color.red, other.red),
+					green: mix(color.green, other.green),
+					blue: mix(color.blue, other.blue),
+				}
+			}
+			Filter::Invert => MateyArgb {
+				alpha: color.alpha,
+				red: 255 - color.red,
+				green: 255 - color.green,
+				blue: 255 - color.blue,
+			},
+		})
+	}
+}
@@ -6,6 +6,13 @@ pub trait CharIndex {
 	fn get_all(&self) -> Self::ElementType;
 
 	fn get(&self, idx: char) -> Option<Self::ElementType>;
+
+	/// Named indexes, like `{primary.css_rgba}`, take precedence over char
+	/// indexes. None of them is made of `r`, `g`, `b` and `a` only, so
+	/// `{primary.rgb}` stays three char indexes.
+	fn get_named(&self, _name: &str) -> Option<Self::ElementType> {
+		None
+	}
 }
 
 impl<T, ET> CharIndex for &T
@@ -21,6 +28,10 @@ where
 	fn get(&self, idx: char) -> Option<Self::ElementType> {
 		(*self).get(idx)
 	}
+
+	fn get_named(&self, name: &str) -> Option<Self::ElementType> {
+		(*self).get_named(name)
+	}
 }
 
 impl CharIndex for MateyArgb {
@@ -45,8 +56,77 @@ impl CharIndex for MateyArgb {
 			format!("{:02X}", value).into_bytes()
 		})
 	}
+
+	fn get_named(&self, name: &str) -> Option<Self::ElementType> {
+		let (r, g, b) = (self.red, self.green, self.blue);
+		let alpha = fraction(self.alpha);
+		let formatted = match name {
+			"hex" => format!("#{}", self.to_hex()),
+			"hex_lower" => self.to_hex().to_lowercase(),
+			"hex_argb" => format!("0x{:02X}{}", self.alpha, self.to_hex()),
+			"css_rgb" => format!("rgb({}, {}, {})", r, g, b),
+			"css_rgba" => format!("rgba({}, {}, {}, {})", r, g, b, alpha),
+			"hsl" | "hsla" => {
+				let (h, s, l) = to_hsl(self);
+				if name == "hsl" {
+					format!("hsl({}, {}%, {}%)", h, s, l)
+				} else {
+					format!("hsla({}, {}%, {}%, {})", h, s, l, alpha)
+				}
+			}
+			"float" => format!("{:.3}, {:.3}, {:.3}", unit(r), unit(g), unit(b)),
+			"float_alpha" => format!(
+				"{:.3}, {:.3}, {:.3}, {:.3}",
+				unit(r),
+				unit(g),
+				unit(b),
+				unit(self.alpha)
+			),
+			_ => return None,
+		};
+
+		Some(formatted.into_bytes())
+	}
+}
+
+fn unit(value: u8) -> f64 {
+	value as f64 / 255.0
+}
+
+/// A channel as a number from 0 to 1 with at most two decimals.
+fn fraction(value: u8) -> f64 {
+	(unit(value) * 100.0).round() / 100.0
+}
+
+/// Returns the hue in degrees, the saturation and lightness in percents.
+fn to_hsl(color: &MateyArgb) -> (u16, u8, u8) {
+	let (r, g, b) = (unit(color.red), unit(color.green), unit(color.blue));
+	let max = r.max(g).max(b);
+	let min = r.min(g).min(b);
+	let delta = max - min;
+	let lightness = (max + min) / 2.0;
+
+	if delta == 0.0 {
+		return (0, 0, (lightness * 100.0).round() as u8);
+	}
+
+	let saturation = delta / (1.0 - (2.0 * lightness - 1.0).abs());
+	let hue = if max == r {
+		((g - b) / delta).rem_euclid(6.0)
+	} else if max == g {
+		(b - r) / delta + 2.0
+	} else {
+		(r - g) / delta + 4.0
+	};
+
+	(
+		((hue * 60.0).round() as u16) % 360,
+		(saturation * 100.0).round() as u8,
+		(lightness * 100.0).round() as u8,
+	)
 }
 
+#[derive(Debug, Clone)]
 pub enum IndexableVariable {
 	Argb(MateyArgb),
 	PlainString(Vec<u8>),
@@ -80,4 +160,46 @@ impl CharIndex for IndexableVariable {
 			IndexableVariable::PlainString(_) => None,
 		}
 	}
+
+	fn get_named(&self, name: &str) -> Option<Self::ElementType> {
+		match self {
+			IndexableVariable::Argb(v) => v.get_named(name),
+			IndexableVariable::PlainString(_) => None,
+		}
+	}
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use crate::{material_newtype::MateyArgb, parsers::templates::CharIndex};
+
+	#[test]
+	fn test_formats() {
+		let color = MateyArgb {
+			alpha: 0x80,
+			red: 0xA0,
+			green: 0xCA,
+			blue: 0xFD,
+		};
+		let named = |name| String::from_utf8(color.get_named(name).unwrap()).unwrap();
+
+		assert_eq!(named("hex"), "#A0CAFD");
+		assert_eq!(named("hex_lower"), "a0cafd");
+		assert_eq!(named("hex_argb"), "0x80A0CAFD");
+		assert_eq!(named("css_rgb"), "rgb(160, 202, 253)");
+		assert_eq!(named("css_rgba"), "rgba(160, 202, 253, 0.5)");
+		assert_eq!(named("hsl"), "hsl(213, 96%, 81%)");
+		assert_eq!(named("hsla"), "hsla(213, 96%, 81%, 0.5)");
+		assert_eq!(named("float"), "0.627, 0.792, 0.992");
+		assert_eq!(named("float_alpha"), "0.627, 0.792, 0.992, 0.502");
+
+		// strings of r, g, b and a are always char indexes
+		for name in ["rgb", "argb", "rgba", "a", "bgr"] {
+			assert_eq!(color.get_named(name), None);
+		}
+		assert_eq!(color.get('r'), Some(b"A0".to_vec()));
+		assert_eq!(color.get('R'), Some(b"160".to_vec()));
+		assert_eq!(color.get('a'), Some(b"80".to_vec()));
+		assert_eq!(color.get('x'), None);
+	}
 }
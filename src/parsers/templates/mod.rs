@@ -1,8 +1,12 @@
 #![allow(clippy::uninlined_format_args)]
 
+mod condition;
+mod filter;
 mod indexable;
 mod template;
+mod variables;
 
 pub use indexable::CharIndex;
 pub use indexable::IndexableVariable;
 pub use template::Template;
+pub use variables::Variables;
@@ -1,13 +1,16 @@
-use std::collections::HashMap;
 use std::io;
 use std::iter::Peekable;
+use std::mem;
 use std::vec::Vec;
 
 use log::warn;
 
+use super::condition::Condition;
+use super::filter::Filter;
 use super::indexable::{CharIndex, IndexableVariable};
+use super::variables::Variables;
 
-use crate::material_newtype::MateyScheme;
+use crate::material_newtype::{MateyArgb, MateyScheme};
 use crate::parsers::common::{RenamingScheme, rename_from_snake_case};
 
 #[derive(Debug)]
@@ -15,11 +18,26 @@ pub enum TemplateToken<'a> {
 	RawString(&'a str),
 	Key(&'a str),
 	IndexedKey(&'a str, &'a str),
+	Filtered(&'a str, &'a str, Vec<Filter<'a>>),
+	Conditional(
+		Condition<'a>,
+		Vec<TemplateToken<'a>>,
+		Vec<TemplateToken<'a>>,
+	),
 }
 
 #[derive(Debug)]
 pub struct Template<'a> {
 	expr: Vec<TemplateToken<'a>>,
+	warnings: Vec<(&'a str, String)>,
+}
+
+/// A key printed by a template, see [`Template::keys`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TemplateKey<'a> {
+	pub key: &'a str,
+	pub indexes: &'a str,
+	pub filtered: bool,
 }
 
 fn peekable_next_until<T: Iterator>(
@@ -45,18 +63,51 @@ fn peekable_next_until<T: Iterator>(
 }
 
 impl<'a> Template<'a> {
+	/// Mistakes in the template are kept in [`Template::warnings`].
 	pub fn new(template: &'a str) -> Self {
 		let mut tokens = Vec::new();
+		let mut warnings = Vec::new();
+		let mut blocks = BlockStack::default();
 		let mut iter = template.char_indices().peekable();
 		let mut escaped = false;
 		while let Some((i, c)) = iter.peek() {
 			match c {
 				'{' if !escaped => {
 					let start = *i + 1;
-					let (end, _) = peekable_next_until(&mut iter, |(_, c)| *c == '}');
+					let (end, last) = peekable_next_until(&mut iter, |(_, c)| *c == '}');
 					iter.next();
-					let whole = &template[start..=end].trim();
-					if let Some(idx) = whole.find('.') {
+					let whole = template[start..end + last.len_utf8()].trim();
+					if let Some(condition) = whole.strip_prefix("if ") {
+						match Condition::parse(condition) {
+							Some(parsed) => blocks.open(&mut tokens, whole, parsed),
+							None => {
+								warnings.push((whole, format!("invalid condition {}", condition)))
+							}
+						}
+					} else if whole == "else" {
+						if let Err(warning) = blocks.otherwise(&mut tokens) {
+							warnings.push((whole, warning));
+						}
+					} else if whole == "end" {
+						if let Err(warning) = blocks.close(&mut tokens) {
+							warnings.push((whole, warning));
+						}
+					} else if let Some((key, filters)) = whole.split_once('|') {
+						let key = key.trim();
+						let (key, indexes) = key.split_once('.').unwrap_or((key, ""));
+						let filters = filters
+							.split('|')
+							.filter_map(|filter| {
+								let parsed = Filter::parse(filter);
+								if parsed.is_none() {
+									let filter = filter.trim();
+									warnings.push((filter, format!("invalid filter {}", filter)));
+								}
+								parsed
+							})
+							.collect();
+						tokens.push(TemplateToken::Filtered(key, indexes, filters));
+					} else if let Some(idx) = whole.find('.') {
 						tokens.push(TemplateToken::IndexedKey(&whole[..idx], &whole[idx + 1..]));
 					} else {
 						tokens.push(TemplateToken::Key(whole));
@@ -69,98 +120,483 @@ impl<'a> Template<'a> {
 				_ => {
 					let start = *i;
 
-					let (end, _) = peekable_next_until(&mut iter, |(_, c)| *c == '{' || *c == '\\');
+					let (end, last) =
+						peekable_next_until(&mut iter, |(_, c)| *c == '{' || *c == '\\');
 					escaped = false;
 
-					tokens.push(TemplateToken::RawString(&template[start..=end]));
+					tokens.push(TemplateToken::RawString(
+						&template[start..end + last.len_utf8()],
+					));
 				}
 			}
 		}
 
-		Template { expr: tokens }
+		while let Some(opening) = blocks.innermost() {
+			warnings.push((opening, "missing {end} for this {if}".to_string()));
+			let _ = blocks.close(&mut tokens);
+		}
+
+		Template {
+			expr: tokens,
+			warnings,
+		}
 	}
 
-	pub fn run_with_hashmap<W>(
-		&self,
-		writer: &mut W,
-		hashmap: &HashMap<String, IndexableVariable>,
-	) -> io::Result<()>
-	where
-		W: io::Write,
-	{
-		for token in &self.expr {
-			match token {
-				TemplateToken::RawString(s) => {
-					writer.write_all(s.as_bytes())?;
-				}
-				TemplateToken::Key(key) => {
-					if let Some(value) = hashmap.get(*key) {
-						writer.write_all(&value.get_all())?;
-					} else {
-						warn!("key \"{key}\" not found");
-					}
-				}
-				TemplateToken::IndexedKey(key, indexes) => {
-					if let Some(value) = hashmap.get(*key) {
-						write_indexed(writer, value, indexes, key, true)?;
-					} else {
-						warn!("warning: key \"{key}\" not found");
+	/// What is wrong in the template, each with the part of the template it
+	/// is about.
+	pub fn warnings(&self) -> &[(&'a str, String)] {
+		&self.warnings
+	}
+
+	/// Every key the template prints, in both branches of `{if}` blocks.
+	/// Keys only used in conditions aren't included, as they fall back to
+	/// being literals.
+	pub fn keys(&self) -> Vec<TemplateKey<'a>> {
+		fn collect<'a>(tokens: &[TemplateToken<'a>], keys: &mut Vec<TemplateKey<'a>>) {
+			for token in tokens {
+				let (key, indexes, filtered) = match token {
+					TemplateToken::RawString(_) => continue,
+					TemplateToken::Key(key) => (*key, "", false),
+					TemplateToken::IndexedKey(key, indexes) => (*key, *indexes, false),
+					TemplateToken::Filtered(key, indexes, _) => (*key, *indexes, true),
+					TemplateToken::Conditional(_, then, otherwise) => {
+						collect(then, keys);
+						collect(otherwise, keys);
+						continue;
 					}
-				}
+				};
+				keys.push(TemplateKey {
+					key,
+					indexes,
+					filtered,
+				});
 			}
 		}
 
-		Ok(())
+		let mut keys = Vec::new();
+		collect(&self.expr, &mut keys);
+		keys
 	}
 
+	pub fn run_with_variables<W>(&self, writer: &mut W, variables: &Variables) -> io::Result<()>
+	where
+		W: io::Write,
+	{
+		run_tokens(&self.expr, writer, &VariablesScope { variables }, false)
+	}
+
+	/// Like [`Template::run_with_variables`], for commands given to `sh`:
+	/// every value is quoted, so `{image}` stays one argument whatever the
+	/// file is called.
+	pub fn run_for_shell<W>(&self, writer: &mut W, variables: &Variables) -> io::Result<()>
+	where
+		W: io::Write,
+	{
+		run_tokens(&self.expr, writer, &VariablesScope { variables }, true)
+	}
+
+	/// The variables answer the keys other than `name` and `color`, in
+	/// conditions and as colors given to filters.
 	pub fn run_with_scheme<W>(
 		&self,
 		writer: &mut W,
 		scheme: &MateyScheme,
 		rename: RenamingScheme,
+		variables: &Variables,
+	) -> io::Result<()>
+	where
+		W: io::Write,
+	{
+		self.run_with_colors(
+			writer,
+			scheme.into_iter().map(|(name, color)| (name, *color)),
+			rename,
+			variables,
+		)
+	}
+
+	/// Runs the template once for every `(name, color)` pair, `name` being in
+	/// snake_case.
+	pub fn run_with_colors<W, N>(
+		&self,
+		writer: &mut W,
+		colors: impl IntoIterator<Item = (N, MateyArgb)>,
+		rename: RenamingScheme,
+		variables: &Variables,
 	) -> io::Result<()>
 	where
 		W: io::Write,
+		N: AsRef<str>,
 	{
 		let mut should_warn = true;
 
-		for (name, color) in scheme {
-			for token in &self.expr {
-				match token {
-					TemplateToken::RawString(s) => {
-						writer.write_all(s.as_bytes())?;
-					}
-					TemplateToken::Key(key) => match *key {
-						"name" => {
-							writer.write_all(rename_from_snake_case(name, rename).as_bytes())?;
-						}
-						"color" => {
-							writer.write_all(color.to_hex().as_bytes())?;
-						}
-						key => {
-							if should_warn {
-								warn!("unknown key \"{key}\" in foreach");
-							}
-						}
-					},
-					TemplateToken::IndexedKey(key, indexes) => match *key {
-						"color" => {
-							write_indexed(writer, color, indexes, key, should_warn)?;
-						}
-						key => {
-							if should_warn {
-								warn!("key \"{key}\" cannot be indexed");
-							}
-						}
-					},
+		for (name, color) in colors {
+			let scope = ColorScope {
+				name: rename_from_snake_case(&name, rename),
+				color,
+				should_warn,
+				variables,
+			};
+			run_tokens(&self.expr, writer, &scope, false)?;
+
+			should_warn = false;
+		}
+
+		Ok(())
+	}
+}
+
+/// The `{if}` that opened a block, its condition, the tokens before it and
+/// the ones before its `{else}`.
+type OpenBlock<'a> = (
+	&'a str,
+	Condition<'a>,
+	Vec<TemplateToken<'a>>,
+	Option<Vec<TemplateToken<'a>>>,
+);
+
+/// Keeps the tokens that come before an `{if}` until the matching `{end}`.
+#[derive(Default)]
+struct BlockStack<'a> {
+	blocks: Vec<OpenBlock<'a>>,
+}
+
+impl<'a> BlockStack<'a> {
+	/// The `{if}` of the innermost open block.
+	fn innermost(&self) -> Option<&'a str> {
+		self.blocks.last().map(|(opening, ..)| *opening)
+	}
+
+	fn open(
+		&mut self,
+		tokens: &mut Vec<TemplateToken<'a>>,
+		opening: &'a str,
+		condition: Condition<'a>,
+	) {
+		self.blocks
+			.push((opening, condition, mem::take(tokens), None));
+	}
+
+	fn otherwise(&mut self, tokens: &mut Vec<TemplateToken<'a>>) -> Result<(), String> {
+		match self.blocks.last_mut() {
+			Some((_, _, _, then @ None)) => *then = Some(mem::take(tokens)),
+			Some((_, _, _, Some(_))) => {
+				return Err("{else} used twice in the same {if} block".to_string());
+			}
+			None => return Err("{else} without {if}".to_string()),
+		}
+
+		Ok(())
+	}
+
+	fn close(&mut self, tokens: &mut Vec<TemplateToken<'a>>) -> Result<(), String> {
+		let Some((_, condition, outer, then)) = self.blocks.pop() else {
+			return Err("{end} without {if}".to_string());
+		};
+
+		let inner = mem::replace(tokens, outer);
+		let (then, otherwise) = match then {
+			Some(then) => (then, inner),
+			None => (inner, Vec::new()),
+		};
+		tokens.push(TemplateToken::Conditional(condition, then, otherwise));
+		Ok(())
+	}
+}
+
+/// What keys mean while running a template.
+trait Scope {
+	fn write_key<W: io::Write>(&self, writer: &mut W, key: &str) -> io::Result<()>;
+
+	fn write_indexed_key<W: io::Write>(
+		&self,
+		writer: &mut W,
+		key: &str,
+		indexes: &str,
+	) -> io::Result<()>;
+
+	/// What `{key}` would print, used by conditions.
+	fn value(&self, key: &str) -> Option<Vec<u8>>;
+
+	/// Like [`resolve_indexed`], but only for colors.
+	fn resolve_color<'i>(&self, key: &str, indexes: &'i str) -> Option<(MateyArgb, &'i str)>;
+
+	fn should_warn(&self) -> bool;
+}
+
+fn write_filtered<W, S>(
+	writer: &mut W,
+	scope: &S,
+	key: &str,
+	indexes: &str,
+	filters: &[Filter],
+) -> io::Result<()>
+where
+	W: io::Write,
+	S: Scope,
+{
+	let Some((mut color, indexes)) = scope.resolve_color(key, indexes) else {
+		if scope.should_warn() {
+			warn!("key \"{key}\" is not a color, filters can't be applied");
+		}
+		return Ok(());
+	};
+
+	let lookup = |other: &str| {
+		let (key, indexes) = other.split_once('.').unwrap_or((other, ""));
+		match scope.resolve_color(key, indexes)? {
+			(color, "") => Some(color),
+			_ => None,
+		}
+	};
+	for filter in filters {
+		match filter.apply(color, &lookup) {
+			Some(filtered) => color = filtered,
+			None if scope.should_warn() => warn!("could not apply {:?} to \"{key}\"", filter),
+			None => {}
+		}
+	}
+
+	if indexes.is_empty() {
+		// `alpha` or `mix` can make it translucent, which RRGGBB would hide
+		writer.write_all(&color.get_all())?;
+		if color.alpha != u8::MAX {
+			write!(writer, "{:02X}", color.alpha)?;
+		}
+		Ok(())
+	} else {
+		write_indexed(writer, color, indexes, key, scope.should_warn())
+	}
+}
+
+/// Runs the tokens, quoting what each key prints for `sh` when `quote` is set,
+/// so a value such as a file name can't be taken for shell syntax.
+fn run_tokens<W, S>(
+	tokens: &[TemplateToken],
+	writer: &mut W,
+	scope: &S,
+	quote: bool,
+) -> io::Result<()>
+where
+	W: io::Write,
+	S: Scope,
+{
+	for token in tokens {
+		match token {
+			TemplateToken::RawString(s) => {
+				writer.write_all(s.as_bytes())?;
+			}
+			TemplateToken::Conditional(condition, then, otherwise) => {
+				if condition.eval(&|key| scope.value(key)) {
+					run_tokens(then, writer, scope, quote)?;
+				} else {
+					run_tokens(otherwise, writer, scope, quote)?;
 				}
 			}
+			key if quote => {
+				let mut value = Vec::new();
+				write_key_token(&mut value, scope, key)?;
+				write_quoted(writer, &value)?;
+			}
+			key => write_key_token(writer, scope, key)?,
+		}
+	}
 
-			should_warn = false;
+	Ok(())
+}
+
+/// Writes what a `Key`, `IndexedKey` or `Filtered` token prints.
+fn write_key_token<W, S>(writer: &mut W, scope: &S, token: &TemplateToken) -> io::Result<()>
+where
+	W: io::Write,
+	S: Scope,
+{
+	match token {
+		TemplateToken::Key(key) => scope.write_key(writer, key),
+		TemplateToken::IndexedKey(key, indexes) => scope.write_indexed_key(writer, key, indexes),
+		TemplateToken::Filtered(key, indexes, filters) => {
+			write_filtered(writer, scope, key, indexes, filters)
+		}
+		TemplateToken::RawString(_) | TemplateToken::Conditional(..) => Ok(()),
+	}
+}
+
+/// Writes `value` as a single `sh` word, in single quotes.
+fn write_quoted<W: io::Write>(writer: &mut W, value: &[u8]) -> io::Result<()> {
+	writer.write_all(b"'")?;
+	for (i, part) in value.split(|&byte| byte == b'\'').enumerate() {
+		if i > 0 {
+			writer.write_all(b"'\\''")?;
+		}
+		writer.write_all(part)?;
+	}
+	writer.write_all(b"'")
+}
+
+struct VariablesScope<'v, 't> {
+	variables: &'v Variables<'t>,
+}
+
+impl Scope for VariablesScope<'_, '_> {
+	fn write_key<W: io::Write>(&self, writer: &mut W, key: &str) -> io::Result<()> {
+		if let Some(value) = self.variables.get(key) {
+			writer.write_all(&value.get_all())?;
+		} else {
+			warn!("key \"{key}\" not found");
 		}
 
 		Ok(())
 	}
+
+	fn write_indexed_key<W: io::Write>(
+		&self,
+		writer: &mut W,
+		key: &str,
+		indexes: &str,
+	) -> io::Result<()> {
+		match resolve_indexed(self.variables, key, indexes) {
+			Some((value, "")) => writer.write_all(&value.get_all()),
+			Some((value, indexes)) => write_indexed(writer, &value, indexes, key, true),
+			None => {
+				warn!("warning: key \"{key}.{indexes}\" not found");
+				Ok(())
+			}
+		}
+	}
+
+	fn value(&self, key: &str) -> Option<Vec<u8>> {
+		match key.split_once('.') {
+			None => self.variables.get(key).map(|value| value.get_all()),
+			Some((key, indexes)) => match resolve_indexed(self.variables, key, indexes)? {
+				(value, "") => Some(value.get_all()),
+				(value, indexes) => index_value(&value, indexes),
+			},
+		}
+	}
+
+	fn resolve_color<'i>(&self, key: &str, indexes: &'i str) -> Option<(MateyArgb, &'i str)> {
+		let (value, indexes) = if indexes.is_empty() {
+			(self.variables.get(key)?, indexes)
+		} else {
+			resolve_indexed(self.variables, key, indexes)?
+		};
+
+		match value {
+			IndexableVariable::Argb(color) => Some((color, indexes)),
+			_ => None,
+		}
+	}
+
+	fn should_warn(&self) -> bool {
+		true
+	}
+}
+
+struct ColorScope<'v, 't> {
+	name: String,
+	color: MateyArgb,
+	should_warn: bool,
+	variables: &'v Variables<'t>,
+}
+
+impl Scope for ColorScope<'_, '_> {
+	fn write_key<W: io::Write>(&self, writer: &mut W, key: &str) -> io::Result<()> {
+		match key {
+			"name" => {
+				writer.write_all(self.name.as_bytes())?;
+			}
+			"color" => {
+				writer.write_all(self.color.to_hex().as_bytes())?;
+			}
+			key => {
+				if self.should_warn {
+					warn!("unknown key \"{key}\" in foreach");
+				}
+			}
+		}
+
+		Ok(())
+	}
+
+	fn write_indexed_key<W: io::Write>(
+		&self,
+		writer: &mut W,
+		key: &str,
+		indexes: &str,
+	) -> io::Result<()> {
+		match key {
+			"color" => {
+				write_indexed(writer, self.color, indexes, key, self.should_warn)?;
+			}
+			key => {
+				if self.should_warn {
+					warn!("key \"{key}\" cannot be indexed");
+				}
+			}
+		}
+
+		Ok(())
+	}
+
+	fn value(&self, key: &str) -> Option<Vec<u8>> {
+		match key.split_once('.') {
+			None if key == "name" => Some(self.name.clone().into_bytes()),
+			None if key == "color" => Some(self.color.get_all()),
+			Some(("color", indexes)) => index_value(self.color, indexes),
+			_ => VariablesScope {
+				variables: self.variables,
+			}
+			.value(key),
+		}
+	}
+
+	/// Other colors can still be used as arguments, e.g. `mix(surface, 0.5)`.
+	fn resolve_color<'i>(&self, key: &str, indexes: &'i str) -> Option<(MateyArgb, &'i str)> {
+		match key {
+			"color" => Some((self.color, indexes)),
+			key => VariablesScope {
+				variables: self.variables,
+			}
+			.resolve_color(key, indexes),
+		}
+	}
+
+	fn should_warn(&self) -> bool {
+		self.should_warn
+	}
+}
+
+/// Finds the longest dotted key that has a value, returning the value and
+/// whatever is left to be used as char indexes.
+fn resolve_indexed<'i>(
+	variables: &Variables,
+	key: &str,
+	indexes: &'i str,
+) -> Option<(IndexableVariable, &'i str)> {
+	let splits = [indexes.len()]
+		.into_iter()
+		.chain(indexes.rmatch_indices('.').map(|(i, _)| i));
+	for split in splits {
+		if let Some(value) = variables.get(&format!("{}.{}", key, &indexes[..split])) {
+			return Some((value, indexes.get(split + 1..).unwrap_or("")));
+		}
+	}
+
+	variables.get(key).map(|value| (value, indexes))
+}
+
+/// What `write_indexed` would write, or `None` if an index doesn't exist.
+fn index_value<I>(value: I, indexes: &str) -> Option<Vec<u8>>
+where
+	I: CharIndex<ElementType = Vec<u8>>,
+{
+	value.get_named(indexes).or_else(|| {
+		indexes
+			.chars()
+			.map(|index| value.get(index))
+			.collect::<Option<Vec<_>>>()
+			.map(|values| values.concat())
+	})
 }
 
 fn write_indexed<W, I>(
@@ -174,6 +610,10 @@ where
 	W: io::Write,
 	I: CharIndex<ElementType = Vec<u8>>,
 {
+	if let Some(v) = value.get_named(indexes) {
+		return writer.write_all(&v);
+	}
+
 	for index in indexes.chars() {
 		if let Some(v) = value.get(index) {
 			writer.write_all(&v)?;
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use super::indexable::IndexableVariable;
+
+use crate::material_newtype::{MateyPalette, MateyScheme, MateyTheme};
+
+/// What the keys of a template refer to. Colors are looked up in the theme
+/// when they are used, so the hundreds of palette tones aren't built for
+/// every template.
+#[derive(Debug)]
+pub struct Variables<'t> {
+	theme: &'t MateyTheme,
+	is_dark: bool,
+	/// Given by the caller, such as `image` or `HOME`.
+	additional: HashMap<String, IndexableVariable>,
+}
+
+impl<'t> Variables<'t> {
+	/// `is_dark` picks the scheme of the keys without a `dark.` or `light.`
+	/// prefix, and the value of `{mode}`.
+	pub fn new(
+		theme: &'t MateyTheme,
+		is_dark: bool,
+		additional: &[(String, IndexableVariable)],
+	) -> Self {
+		Variables {
+			theme,
+			is_dark,
+			additional: additional.iter().cloned().collect(),
+		}
+	}
+
+	/// The same variables with another mode, for `foreach dark` and
+	/// `foreach light`.
+	pub fn with_mode(&self, is_dark: bool) -> Self {
+		Variables {
+			theme: self.theme,
+			is_dark,
+			additional: self.additional.clone(),
+		}
+	}
+
+	/// The scheme picked by the mode.
+	pub fn scheme(&self) -> &'t MateyScheme {
+		if self.is_dark {
+			&self.theme.dark
+		} else {
+			&self.theme.light
+		}
+	}
+
+	pub fn mode(&self) -> &'static str {
+		if self.is_dark { "dark" } else { "light" }
+	}
+
+	/// The value of a whole key, such as `primary`, `dark.primary` or
+	/// `palette.primary.40`.
+	pub fn get(&self, key: &str) -> Option<IndexableVariable> {
+		if let Some(value) = self.additional.get(key) {
+			return Some(value.clone());
+		}
+
+		let color = |scheme: &MateyScheme, name: &str| {
+			scheme
+				.into_iter()
+				.find_map(|(field, color)| (field == name).then_some((*color).into()))
+		};
+
+		match key.split_once('.') {
+			None => match key {
+				"variant" => Some(IndexableVariable::plain(
+					self.theme.variant.name().as_bytes().to_vec(),
+				)),
+				"seed" => Some(self.theme.source.into()),
+				"mode" => Some(IndexableVariable::plain(self.mode().as_bytes().to_vec())),
+				name => color(self.scheme(), name),
+			},
+			Some(("dark", name)) => color(&self.theme.dark, name),
+			Some(("light", name)) => color(&self.theme.light, name),
+			Some(("palette", rest)) => {
+				let (name, tone) = rest.split_once('.')?;
+				let tone = MateyPalette::parse_tone(tone)?;
+				Some(self.theme.palettes.get(name)?.tone(tone)?.into())
+			}
+			_ => None,
+		}
+	}
+}
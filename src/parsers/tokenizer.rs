@@ -1,20 +1,20 @@
-use crate::error::{Error, Fallible};
+use crate::diagnostic::{Diagnostic, Span};
 
-use super::common::FileLocation;
-
-use std::ffi::OsString;
 use std::iter::Peekable;
+use std::path::PathBuf;
 use std::str::CharIndices;
 
+/// `span` covers the whole token, quotes and braces included, while `source`
+/// is only what is inside them.
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub struct Token<'a> {
 	pub source: &'a str,
-	pub location: FileLocation,
+	pub span: Span,
 }
 
 impl<'source> Token<'source> {
-	fn new(source: &'source str, location: FileLocation) -> Self {
-		Token { source, location }
+	fn new(source: &'source str, span: Span) -> Self {
+		Token { source, span }
 	}
 }
 
@@ -31,91 +31,108 @@ pub enum ConfigToken<'a> {
 }
 
 struct Tokenizer<'a> {
-	path: OsString,
+	path: PathBuf,
 	source: &'a str,
 	iter: Peekable<CharIndices<'a>>,
-	location: FileLocation,
 }
 
 impl<'source> Tokenizer<'source> {
-	fn new(source: &'source str, path: OsString) -> Self {
+	fn new(source: &'source str, path: PathBuf) -> Self {
 		Tokenizer {
 			path,
 			source,
 			iter: source.char_indices().peekable(),
-			location: FileLocation::new(),
 		}
 	}
 
-	fn tokenize_option_command(&mut self) -> Fallible<ConfigToken<'source>> {
-		let start = self.expect('#')? + 1;
+	/// The offset of the next character.
+	fn position(&mut self) -> usize {
+		self.iter.peek().map_or(self.source.len(), |(i, _)| *i)
+	}
+
+	fn tokenize_option_command(&mut self) -> Result<ConfigToken<'source>, Box<Diagnostic>> {
+		let hash = self.expect('#')?;
 
-		let mut end = start - 1;
-		while self.iter.next_if(|(_, c)| c.is_alphabetic()).is_some() {
-			self.location.step();
-			end += 1;
-		}
+		while self.iter.next_if(|(_, c)| c.is_alphabetic()).is_some() {}
+		let end = self.position();
 
-		if end <= start {
-			return self.error("Expected command after #".to_string()).into();
+		if end == hash + 1 {
+			return Err(self.error(
+				Span::new(hash, end),
+				"expected a command after #",
+				Some("commands look like `#out \"path\"`"),
+			));
 		}
 
 		Ok(ConfigToken::OptionCommand(Token::new(
-			&self.source[start..=end],
-			self.location,
+			&self.source[hash + 1..end],
+			Span::new(hash, end),
 		)))
 	}
 
-	fn tokenize_id(&mut self) -> Fallible<ConfigToken<'source>> {
+	fn tokenize_id(&mut self) -> Result<ConfigToken<'source>, Box<Diagnostic>> {
 		let start = if let Some((i, _)) = self.iter.next() {
 			i
 		} else {
 			unreachable!();
 		};
 
-		let mut end = start;
-		while self.iter.next_if(|(_, c)| c.is_alphanumeric()).is_some() {
-			self.location.step();
-			end += 1;
-		}
+		while self
+			.iter
+			.next_if(|(_, c)| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'))
+			.is_some()
+		{}
+		let end = self.position();
 
 		Ok(ConfigToken::Id(Token::new(
-			&self.source[start..=end],
-			self.location,
+			&self.source[start..end],
+			Span::new(start, end),
+		)))
+	}
+
+	/// Numbers such as `0.5` or `-1`, only checked when they are used.
+	fn tokenize_number(&mut self) -> Result<ConfigToken<'source>, Box<Diagnostic>> {
+		let start = self.position();
+		self.iter.next_if(|(_, c)| matches!(c, '-' | '+'));
+		while self
+			.iter
+			.next_if(|(_, c)| c.is_ascii_digit() || *c == '.')
+			.is_some()
+		{}
+		let end = self.position();
+
+		Ok(ConfigToken::Number(Token::new(
+			&self.source[start..end],
+			Span::new(start, end),
 		)))
 	}
 
-	fn tokenize_literal(&mut self) -> Fallible<ConfigToken<'source>> {
-		let (start, is_double) = if let Ok(pos) = self.expect('"') {
-			(pos + 1, true)
+	fn tokenize_literal(&mut self) -> Result<ConfigToken<'source>, Box<Diagnostic>> {
+		let (quote, is_double) = if let Ok(pos) = self.expect('"') {
+			(pos, true)
 		} else {
-			(self.expect('\'')? + 1, false)
+			(self.expect('\'')?, false)
 		};
+		let start = quote + 1;
 
 		let mut is_template = false;
-		let mut end = start - 1;
-		for (i, c) in self.iter.by_ref() {
-			self.location.step();
+		let mut end = None;
+		while let Some((i, c)) = self.iter.next_if(|(_, c)| *c != '\n') {
 			match c {
 				'"' => {
 					if !is_double {
 						continue;
 					}
-					end = i;
+					end = Some(i);
 					break;
 				}
 				'\'' => {
 					if is_double {
 						continue;
 					}
-					end = i;
+					end = Some(i);
 					break;
 				}
-				'\n' => {
-					return self
-						.error("No matching quotes found for string".to_string())
-						.into();
-				}
 				'{' => {
 					is_template = true;
 				}
@@ -123,34 +140,37 @@ impl<'source> Tokenizer<'source> {
 			}
 		}
 
-		if end < start {
-			return self.error("String doesn't end".to_string()).into();
-		}
+		let Some(end) = end else {
+			let end = self.position();
+			return Err(self.error(
+				Span::new(quote, end),
+				"this string doesn't end",
+				Some(if is_double {
+					"add a `\"` before the end of the line"
+				} else {
+					"add a `'` before the end of the line"
+				}),
+			));
+		};
 
+		let token = Token::new(&self.source[start..end], Span::new(quote, end + 1));
 		if is_template {
-			Ok(ConfigToken::TemplateBlock(Token::new(
-				&self.source[start..end],
-				self.location,
-			)))
+			Ok(ConfigToken::TemplateBlock(token))
 		} else {
-			Ok(ConfigToken::Literal(Token::new(
-				&self.source[start..end],
-				self.location,
-			)))
+			Ok(ConfigToken::Literal(token))
 		}
 	}
 
-	fn tokenize_template_block(&mut self) -> Fallible<ConfigToken<'source>> {
-		let start = self.expect('{')?;
+	fn tokenize_template_block(&mut self) -> Result<ConfigToken<'source>, Box<Diagnostic>> {
+		let brace = self.expect('{')?;
 
 		let start = if let Some((i, _)) = self.iter.next_if(|(_, c)| *c == '\n') {
-			self.location.nl();
 			i
 		} else {
-			start
+			brace
 		} + 1;
 
-		let mut end = start - 1;
+		let mut end = None;
 		let mut opened = 0;
 		for (i, c) in self.iter.by_ref() {
 			match c {
@@ -159,43 +179,39 @@ impl<'source> Tokenizer<'source> {
 				}
 				'}' => {
 					if opened == 0 {
-						end = i;
+						end = Some(i);
 						break;
 					}
 					opened -= 1;
 				}
-				'\n' => {
-					self.location.nl();
-				}
-				_ => {
-					self.location.step();
-				}
+				_ => {}
 			}
 		}
 
-		if end < start {
-			return self.error("No matching left brace".to_string()).into();
-		}
+		let Some(end) = end else {
+			return Err(self.error(
+				Span::new(brace, brace + 1),
+				"this template block is never closed",
+				Some("add a matching `}`"),
+			));
+		};
 
 		Ok(ConfigToken::TemplateBlock(Token::new(
 			&self.source[start..end],
-			self.location,
+			Span::new(brace, end + 1),
 		)))
 	}
 
-	fn tokenize_next(&mut self) -> Fallible<ConfigToken<'source>> {
+	fn tokenize_next(&mut self) -> Result<ConfigToken<'source>, Box<Diagnostic>> {
 		let mut next = Ok(ConfigToken::Eof);
-		while let Some((_, c)) = self.iter.peek() {
+		while let Some(&(i, c)) = self.iter.peek() {
 			next = match c {
 				'#' => self.tokenize_option_command(),
 				'{' => self.tokenize_template_block(),
 				'"' | '\'' => self.tokenize_literal(),
 				'\n' => {
-					self.location.nl();
 					self.iter.next();
-					while self.iter.next_if(|(_, c)| *c == '\n').is_some() {
-						self.location.nl();
-					}
+					while self.iter.next_if(|(_, c)| *c == '\n').is_some() {}
 					Ok(ConfigToken::NewLine)
 				}
 				c if c.is_whitespace() => {
@@ -203,7 +219,15 @@ impl<'source> Tokenizer<'source> {
 					continue;
 				}
 				c if c.is_alphabetic() => self.tokenize_id(),
-				&c => self.error(format!("Unexpected token {}", c)).into(),
+				c if c.is_ascii_digit() || matches!(c, '-' | '+' | '.') => self.tokenize_number(),
+				c => {
+					self.iter.next();
+					Err(self.error(
+						Span::new(i, i + c.len_utf8()),
+						format!("unexpected character {:?}", c),
+						None,
+					))
+				}
 			};
 			break;
 		}
@@ -211,32 +235,29 @@ impl<'source> Tokenizer<'source> {
 		next
 	}
 
-	fn expect(&mut self, c: char) -> Fallible<usize> {
-		match self.iter.next() {
-			Some((i, got)) if got == c => {
-				if c == '\n' {
-					self.location.nl();
-				} else {
-					self.location.step();
-				}
-				Ok(i)
-			}
-			Some((_, got)) => self.error(format!("expected {}, got {}", c, got)).into(),
-			None => self.error(format!("expected {}, got EOF", c)).into(),
+	fn expect(&mut self, c: char) -> Result<usize, Box<Diagnostic>> {
+		let position = self.position();
+		match self.iter.next_if(|(_, got)| *got == c) {
+			Some((i, _)) => Ok(i),
+			None => Err(self.error(
+				Span::new(position, position + 1),
+				format!("expected {:?}", c),
+				None,
+			)),
 		}
 	}
 
-	fn error(&self, reason: String) -> Error {
-		Error::tokenizing_error(format!("({}) {}", self.location(), reason))
-	}
-
-	fn location(&self) -> String {
-		format!("in {:#?} at {}", self.path, self.location)
+	fn error(&self, span: Span, message: impl Into<String>, hint: Option<&str>) -> Box<Diagnostic> {
+		let mut diagnostic = Diagnostic::error(&self.path, self.source, span, message);
+		if let Some(hint) = hint {
+			diagnostic = diagnostic.with_hint(hint);
+		}
+		Box::new(diagnostic)
 	}
 }
 
 impl<'a> Iterator for Tokenizer<'a> {
-	type Item = Fallible<ConfigToken<'a>>;
+	type Item = Result<ConfigToken<'a>, Box<Diagnostic>>;
 
 	fn next(&mut self) -> Option<Self::Item> {
 		match self.tokenize_next() {
@@ -249,7 +270,7 @@ impl<'a> Iterator for Tokenizer<'a> {
 
 pub fn parse_source<'source>(
 	source: &'source str,
-	path: OsString,
-) -> impl IntoIterator<Item = Fallible<ConfigToken<'source>>> {
+	path: PathBuf,
+) -> impl IntoIterator<Item = Result<ConfigToken<'source>, Box<Diagnostic>>> {
 	Tokenizer::new(source, path)
 }
@@ -0,0 +1,87 @@
+use material_colors::image::{AsPixels, FilterType, Image};
+use material_colors::quantize::{Quantizer, QuantizerCelebi};
+use material_colors::score::Score;
+
+use crate::error::{Error, Fallible};
+use crate::material_newtype::MateyArgb;
+
+pub const MAX_CANDIDATES: usize = 8;
+
+/// Returns the colors of the image that are suitable as a theme source, the
+/// best one first. There is always at least one candidate.
+pub fn extract_candidates(buffer: &[u8]) -> Fallible<Vec<MateyArgb>> {
+	// material-colors panics on images it can't decode, so it only gets the
+	// pixels
+	let decoded = image::load_from_memory(buffer).map_err(|e| Error::Image {
+		path: None,
+		source: Box::new(e),
+	})?;
+	let mut image = Image::new(decoded.into_rgba8());
+	image.resize(128, 128, FilterType::Lanczos3);
+
+	let result = QuantizerCelebi::quantize(&image.as_pixels(), 128);
+	let ranked = Score::score(
+		&result.color_to_count,
+		Some(MAX_CANDIDATES as i32),
+		None,
+		None,
+	);
+
+	Ok(ranked.into_iter().map(MateyArgb::from).collect())
+}
+
+/// The candidate at `index`, as listed by `matey candidates`.
+pub fn select_source(buffer: &[u8], index: usize) -> Fallible<MateyArgb> {
+	let candidates = extract_candidates(buffer)?;
+	candidates.get(index).copied().ok_or_else(|| {
+		Error::Usage(format!(
+			"source index {} is out of range, the image only has {} candidates (see matey candidates)",
+			index,
+			candidates.len()
+		))
+	})
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use crate::{
+		error::Error,
+		source::{MAX_CANDIDATES, extract_candidates, select_source},
+	};
+	#[allow(unused_imports)]
+	use image::{ImageFormat, RgbImage};
+	#[allow(unused_imports)]
+	use std::io::Cursor;
+
+	#[test]
+	fn test_candidates() {
+		// mostly blue with an orange stripe
+		let image = RgbImage::from_fn(64, 64, |x, _| {
+			if x < 48 {
+				image::Rgb([40, 80, 200])
+			} else {
+				image::Rgb([230, 120, 30])
+			}
+		});
+		let mut png = Cursor::new(Vec::new());
+		image.write_to(&mut png, ImageFormat::Png).unwrap();
+		let png = png.into_inner();
+
+		let candidates = extract_candidates(&png).unwrap();
+		assert!((1..=MAX_CANDIDATES).contains(&candidates.len()));
+		assert!(candidates[0].blue > candidates[0].red);
+
+		for (index, candidate) in candidates.iter().enumerate() {
+			assert_eq!(select_source(&png, index).unwrap(), *candidate);
+		}
+		assert!(matches!(
+			select_source(&png, candidates.len()),
+			Err(Error::Usage(_))
+		));
+
+		assert!(matches!(
+			extract_candidates(b"not an image"),
+			Err(Error::Image { .. })
+		));
+	}
+}
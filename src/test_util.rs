@@ -0,0 +1,57 @@
+//! What the `mod test` blocks share.
+#![allow(dead_code)]
+
+use std::{
+	env, fs,
+	ops::Deref,
+	path::{Path, PathBuf},
+	process,
+};
+
+use material_colors::color::Argb;
+
+use crate::material_newtype::{MateyTheme, MateyVariant};
+
+/// The color the test themes are generated from.
+pub fn test_source() -> Argb {
+	Argb::from_u32(0xff3366ff)
+}
+
+/// The theme of [`test_source`] with the default variant and contrast.
+pub fn test_theme() -> MateyTheme {
+	MateyTheme::from_source(test_source(), MateyVariant::TonalSpot, 0.0)
+}
+
+/// A folder in the temporary folder, removed when dropped, so also when the
+/// test fails. It isn't created, as some tests check that it gets created.
+pub struct TempFolder(PathBuf);
+
+impl TempFolder {
+	/// `name` keeps the tests apart, and the process id the test runs.
+	pub fn new(name: &str) -> Self {
+		let path = env::temp_dir().join(format!("matey-test-{}-{}", name, process::id()));
+		// left over from a run that was killed
+		let _ = fs::remove_dir_all(&path);
+		TempFolder(path)
+	}
+}
+
+impl Deref for TempFolder {
+	type Target = Path;
+
+	fn deref(&self) -> &Path {
+		&self.0
+	}
+}
+
+impl AsRef<Path> for TempFolder {
+	fn as_ref(&self) -> &Path {
+		&self.0
+	}
+}
+
+impl Drop for TempFolder {
+	fn drop(&mut self) {
+		let _ = fs::remove_dir_all(&self.0);
+	}
+}
@@ -0,0 +1,203 @@
+use std::{
+	collections::HashMap,
+	ffi::OsStr,
+	fs,
+	io::ErrorKind,
+	path::{Path, PathBuf},
+	thread,
+	time::Duration,
+};
+
+use inotify::{Event, Inotify, WatchDescriptor, WatchMask};
+
+use crate::error::{Error, Fallible};
+
+/// Editors and `ln -sf` touch a file several times in a row, events that come
+/// within this delay of each other are reported together.
+const DEBOUNCE: Duration = Duration::from_millis(150);
+
+/// Watches files and folders with inotify.
+///
+/// Files are watched through their folder, so that replacing them (which is
+/// what most editors and `ln -sf` do) is noticed too. When a file is a
+/// symlink, the folder of its target is watched as well.
+pub struct Watcher {
+	inotify: Inotify,
+	folders: HashMap<WatchDescriptor, PathBuf>,
+	buffer: Vec<u8>,
+}
+
+impl Watcher {
+	pub fn new() -> Fallible<Watcher> {
+		let inotify = Inotify::init().map_err(|e| Error::IO {
+			message: "could not initialize inotify".to_string(),
+			path: None,
+			source: Some(e),
+		})?;
+
+		Ok(Watcher {
+			inotify,
+			folders: HashMap::new(),
+			buffer: vec![0; 4096],
+		})
+	}
+
+	pub fn watch_file(&mut self, path: &Path) -> Fallible<()> {
+		if let Some(folder) = path.parent() {
+			self.watch_folder(folder)?;
+		}
+
+		if path.is_symlink()
+			&& let Ok(target) = fs::canonicalize(path)
+			&& let Some(folder) = target.parent()
+		{
+			self.watch_folder(folder)?;
+		}
+
+		Ok(())
+	}
+
+	/// Adding the same folder twice is harmless.
+	pub fn watch_folder(&mut self, path: &Path) -> Fallible<()> {
+		let path = if path.as_os_str().is_empty() {
+			Path::new(".")
+		} else {
+			path
+		};
+		let mask = WatchMask::CLOSE_WRITE
+			| WatchMask::CREATE
+			| WatchMask::DELETE
+			| WatchMask::MOVED_TO
+			| WatchMask::MOVED_FROM;
+
+		let wd = self
+			.inotify
+			.watches()
+			.add(path, mask)
+			.map_err(|e| Error::io("could not watch", path, e))?;
+		self.folders.insert(wd, path.to_path_buf());
+
+		Ok(())
+	}
+
+	/// Blocks until something changes in the watched folders and returns the
+	/// paths that changed.
+	pub fn wait(&mut self) -> Fallible<Vec<PathBuf>> {
+		let mut changed = Vec::new();
+
+		let events = self
+			.inotify
+			.read_events_blocking(&mut self.buffer)
+			.map_err(Error::from_io)?;
+		collect_paths(&self.folders, events, &mut changed);
+
+		loop {
+			thread::sleep(DEBOUNCE);
+			match self.inotify.read_events(&mut self.buffer) {
+				Ok(events) => collect_paths(&self.folders, events, &mut changed),
+				Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+				Err(e) => return Err(Error::from_io(e)),
+			}
+		}
+
+		Ok(changed)
+	}
+}
+
+/// Whether `path` is one of the paths [`Watcher::wait`] returned, itself or,
+/// when it is a symlink, the file it points to.
+pub fn file_changed(path: &Path, changed: &[PathBuf]) -> bool {
+	let target = fs::canonicalize(path).ok();
+	changed
+		.iter()
+		.any(|changed| changed == path || Some(changed) == target.as_ref())
+}
+
+fn collect_paths<'a>(
+	folders: &HashMap<WatchDescriptor, PathBuf>,
+	events: impl Iterator<Item = Event<&'a OsStr>>,
+	changed: &mut Vec<PathBuf>,
+) {
+	for event in events {
+		let (Some(folder), Some(name)) = (folders.get(&event.wd), event.name) else {
+			continue;
+		};
+
+		let path = folder.join(name);
+		if !changed.contains(&path) {
+			changed.push(path);
+		}
+	}
+}
+
+mod test {
+	#[allow(unused_imports)]
+	use crate::{
+		test_util::TempFolder,
+		watch::{Watcher, file_changed},
+	};
+	#[allow(unused_imports)]
+	#[cfg(unix)]
+	use std::os::unix::fs::symlink;
+	#[allow(unused_imports)]
+	use std::{fs, slice};
+
+	#[test]
+	fn test_watcher() {
+		let folder = TempFolder::new("watch");
+		let templates = folder.join("templates");
+		fs::create_dir_all(&templates).unwrap();
+		let template = templates.join("kitty.conf");
+		fs::write(&template, "one").unwrap();
+
+		let mut watcher = Watcher::new().unwrap();
+		watcher.watch_file(&template).unwrap();
+
+		// written twice, reported once
+		fs::write(&template, "two").unwrap();
+		fs::write(&template, "three").unwrap();
+		assert_eq!(watcher.wait().unwrap(), slice::from_ref(&template));
+
+		// editors write a copy and move it over the file
+		let copy = templates.join(".kitty.conf.swp");
+		fs::write(&copy, "four").unwrap();
+		fs::rename(&copy, &template).unwrap();
+		let changed = watcher.wait().unwrap();
+		assert!(changed.contains(&template));
+		assert!(file_changed(&template, &changed));
+		assert!(!file_changed(&templates.join("other.conf"), &changed));
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn test_image_link() {
+		let folder = TempFolder::new("watch-link");
+		let (links, walls) = (folder.join("links"), folder.join("walls"));
+		fs::create_dir_all(&links).unwrap();
+		fs::create_dir_all(&walls).unwrap();
+		let (forest, beach) = (walls.join("forest.png"), walls.join("beach.png"));
+		fs::write(&forest, "forest").unwrap();
+		fs::write(&beach, "beach").unwrap();
+		let image = links.join("current");
+		symlink(&forest, &image).unwrap();
+
+		let mut watcher = Watcher::new().unwrap();
+		watcher.watch_file(&image).unwrap();
+
+		// the image is written in place, through the folder of the target
+		fs::write(&forest, "forest, edited").unwrap();
+		let changed = watcher.wait().unwrap();
+		assert!(!changed.contains(&image));
+		assert!(file_changed(&image, &changed));
+
+		// ln -sf removes the link and makes a new one
+		fs::remove_file(&image).unwrap();
+		symlink(&beach, &image).unwrap();
+		let changed = watcher.wait().unwrap();
+		assert!(file_changed(&image, &changed));
+
+		// the old target isn't the image anymore
+		fs::write(&forest, "forest, again").unwrap();
+		assert!(!file_changed(&image, &watcher.wait().unwrap()));
+	}
+}